@@ -5,7 +5,7 @@ use crossterm::event::Event;
 use crate::{
     app_state::{
         app_transition::AppTransition, configure_mapping_state::ConfigureMappingState,
-        selecting_input_state::SelectingInputState, AppState,
+        confirm_plan_state::ConfirmPlanState, selecting_input_state::SelectingInputState, AppState,
     },
     dao::Dao,
 };
@@ -14,6 +14,7 @@ pub struct App {
     // dao: Rc<RefCell<Dao>>,
     pub selecting_input_state: SelectingInputState,
     pub configure_mapping_state: Option<ConfigureMappingState>,
+    pub confirm_plan_state: Option<ConfirmPlanState>,
 }
 
 pub enum AppResult {
@@ -32,10 +33,14 @@ impl App {
                 out_dir.to_string(),
             ),
             configure_mapping_state: None,
+            confirm_plan_state: None,
         }
     }
 
     fn current_state_mut(&mut self) -> &mut dyn AppState {
+        if let Some(cps) = self.confirm_plan_state.as_mut() {
+            return cps;
+        }
         if let Some(cms) = self.configure_mapping_state.as_mut() {
             return cms;
         }
@@ -47,6 +52,16 @@ impl App {
         self.handle_transition(t)
     }
 
+    // picks up the results of background work, e.g. CRC checks
+    pub fn on_tick(&mut self) {
+        if !self.selecting_input_state.poll_crc_check() {
+            return;
+        }
+        if let Some(cms) = self.configure_mapping_state.as_mut() {
+            cms.crc_mismatches = self.selecting_input_state.crc_mismatches(&cms.mapped_dir);
+        }
+    }
+
     fn handle_transition(&mut self, transition: AppTransition) -> AppResult {
         match transition {
             AppTransition::None => AppResult::KeepGoing,
@@ -57,9 +72,13 @@ impl App {
                     .get(mapping_idx)
                     .unwrap();
 
+                let mapped_dir = mapping.to_mapped_dir();
+                self.selecting_input_state.log_scan_errors(&mapped_dir);
+                let crc_mismatches = self.selecting_input_state.crc_mismatches(&mapped_dir);
                 self.configure_mapping_state = Some(ConfigureMappingState::new(
                     mapping_idx,
-                    mapping.to_mapped_dir(),
+                    mapped_dir,
+                    crc_mismatches,
                 ));
                 AppResult::KeepGoing
             }
@@ -67,9 +86,20 @@ impl App {
                 self.configure_mapping_state = None;
                 AppResult::KeepGoing
             }
-            AppTransition::CommitConfiguration(idx, mapped_dir) => {
+            AppTransition::ReviewConfiguration(idx, mapped_dir) => {
+                let plan = self.selecting_input_state.plan_mapping(idx, &mapped_dir);
+                self.confirm_plan_state = Some(ConfirmPlanState::new(idx, mapped_dir, plan));
+                AppResult::KeepGoing
+            }
+            AppTransition::AbortReview => {
+                self.confirm_plan_state = None;
+                AppResult::KeepGoing
+            }
+            AppTransition::CommitConfiguration(idx, mapped_dir, plan) => {
+                self.confirm_plan_state = None;
                 self.configure_mapping_state = None;
-                self.selecting_input_state.commit_mapping(idx, mapped_dir);
+                self.selecting_input_state
+                    .commit_mapping(idx, mapped_dir, &plan);
                 AppResult::KeepGoing
             }
             AppTransition::Quit => AppResult::Quit,
@@ -1,3 +1,5 @@
+use crate::plan::Plan;
+
 use super::mapping_state::MappedDir;
 
 pub enum AppTransition {
@@ -5,5 +7,7 @@ pub enum AppTransition {
     Quit,
     StartConfiguringIdx(usize),
     AbortConfiguration,
-    CommitConfiguration(usize, MappedDir),
+    ReviewConfiguration(usize, MappedDir),
+    AbortReview,
+    CommitConfiguration(usize, MappedDir, Plan),
 }
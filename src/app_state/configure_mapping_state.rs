@@ -1,10 +1,26 @@
-use super::{app_transition::AppTransition, mapping_state::MappedDir, AppState};
+use std::collections::HashSet;
+
+use super::{
+    app_transition::AppTransition,
+    mapping_state::{FileMapping, MappedDir, RULE_FILTER, RULE_MATCHER, RULE_REPLACER},
+    AppState,
+};
+use crate::{
+    path_utils::{join_path, resolve_parents},
+    widgets::tree_list::TreeListState,
+};
 use crossterm::event::{Event, KeyCode, KeyEvent};
 use tui::{
     interactive_form::InteractiveForm,
     widgets::{InteractiveWidgetState, TextInputState},
 };
 
+// top level dirs of the output tree for files that won't be linked
+pub const FILTERED_TREE_DIR: &str = "(filtered)";
+pub const UNMATCHED_TREE_DIR: &str = "(no match)";
+pub const SUPERSEDED_TREE_DIR: &str = "(superseded)";
+pub const JUNK_TREE_DIR: &str = "(junk)";
+
 #[tui::macros::interactive_form]
 pub struct ConfigureMappingFormState {
     #[default("mkv,mp4,avi")]
@@ -17,20 +33,129 @@ pub struct ConfigureMappingFormState {
     pub file_matcher_input_state: TextInputState,
     #[default("$1")]
     pub file_replacer_input_state: TextInputState,
+    #[default("")]
+    pub rule_filter_input_state: TextInputState,
+    #[default("0")]
+    pub offset_input_state: TextInputState,
+    #[default("")]
+    pub specials_input_state: TextInputState,
+    #[default("")]
+    pub junk_input_state: TextInputState,
+    #[default("")]
+    pub example_input_state: TextInputState,
+    pub output_tree_state: TreeListState,
 }
 
 pub struct ConfigureMappingState {
     pub mapping_idx: usize,
     pub mapped_dir: MappedDir,
     pub form: ConfigureMappingFormState,
+    pub selected_rule: usize,
+    pub selected_file: usize,
+    pub example_error: Option<String>,
+    // input files whose computed CRC differs from the one in their name
+    pub crc_mismatches: HashSet<String>,
 }
 
 impl ConfigureMappingState {
-    pub fn new(mapping_idx: usize, mapped_dir: MappedDir) -> ConfigureMappingState {
-        ConfigureMappingState {
+    pub fn new(
+        mapping_idx: usize,
+        mapped_dir: MappedDir,
+        crc_mismatches: HashSet<String>,
+    ) -> ConfigureMappingState {
+        let mut ret = ConfigureMappingState {
             mapping_idx,
             mapped_dir,
             form: Default::default(),
+            selected_rule: 0,
+            selected_file: 0,
+            example_error: None,
+            crc_mismatches,
+        };
+        ret.load_form();
+        ret.load_output_tree();
+        ret
+    }
+
+    fn load_form(&mut self) {
+        let form = &mut self.form;
+        let mapped_dir = &self.mapped_dir;
+        form.file_ext_input_state.set_value(mapped_dir.config(0));
+        form.dir_matcher_input_state.set_value(mapped_dir.config(1));
+        form.dir_replacer_input_state
+            .set_value(mapped_dir.config(2));
+        form.offset_input_state.set_value(mapped_dir.config(3));
+        form.specials_input_state.set_value(mapped_dir.config(4));
+        form.junk_input_state.set_value(mapped_dir.config(5));
+        self.load_rule_form();
+    }
+
+    // the file matcher / replacer / filter inputs edit the selected rule
+    fn load_rule_form(&mut self) {
+        let rule = &self.mapped_dir.file_rules()[self.selected_rule];
+        let form = &mut self.form;
+        form.file_matcher_input_state
+            .set_value(rule.config(RULE_MATCHER));
+        form.file_replacer_input_state
+            .set_value(rule.config(RULE_REPLACER));
+        form.rule_filter_input_state
+            .set_value(rule.config(RULE_FILTER));
+    }
+
+    // what the output dir will look like, along with the files that won't be in it
+    fn load_output_tree(&mut self) {
+        let out_dir_name = self
+            .mapped_dir
+            .out_dir_name()
+            .unwrap_or_else(|| "(no output dir)".to_string());
+        let paths = self
+            .mapped_dir
+            .file_mappings()
+            .iter()
+            .map(|file_mapping| match file_mapping {
+                FileMapping::MappedTo { to_name, .. } => {
+                    resolve_parents(&join_path(&out_dir_name, to_name))
+                }
+                FileMapping::Filtered { name } => join_path(FILTERED_TREE_DIR, name),
+                FileMapping::Unmatched { name } => join_path(UNMATCHED_TREE_DIR, name),
+                FileMapping::Superseded { name, .. } => join_path(SUPERSEDED_TREE_DIR, name),
+                FileMapping::Junk { name } => join_path(JUNK_TREE_DIR, name),
+            })
+            .collect();
+        self.form.output_tree_state.set_paths(paths);
+    }
+
+    fn select_rule(&mut self, rule_idx: usize) -> AppTransition {
+        self.selected_rule = rule_idx;
+        self.load_rule_form();
+        AppTransition::None
+    }
+
+    fn select_file(&mut self, file_idx: usize) -> AppTransition {
+        self.selected_file = file_idx;
+        self.form.example_input_state.set_value("");
+        self.example_error = None;
+        AppTransition::None
+    }
+
+    // rewrites the selected rule so the selected file is renamed to the example output, once
+    // it's confirmed with Enter, half typed examples would clobber the rule
+    fn apply_example(&mut self) {
+        let out_name = self.form.example_input_state.get_value().to_string();
+        if out_name.is_empty() || self.mapped_dir.file_mappings().is_empty() {
+            self.example_error = None;
+            return;
+        }
+
+        match self
+            .mapped_dir
+            .apply_example(self.selected_rule, self.selected_file, &out_name)
+        {
+            Ok(()) => {
+                self.example_error = None;
+                self.load_rule_form();
+            }
+            Err(e) => self.example_error = Some(e),
         }
     }
 }
@@ -45,21 +170,43 @@ macro_rules! update_if_changed {
     };
 }
 
+macro_rules! update_rule_if_changed {
+    ($idx:expr, $self:ident, $field:ident) => {
+        if $self.form.$field.changed() {
+            $self.mapped_dir.set_rule_config(
+                $self.selected_rule,
+                $idx,
+                $self.form.$field.get_value(),
+            );
+        }
+    };
+}
+
 impl AppState for ConfigureMappingState {
     fn on_event(&mut self, event: Event) -> AppTransition {
         if self.form.handle_event(event).is_consumed() {
             update_if_changed!(0, self, file_ext_input_state);
             update_if_changed!(1, self, dir_matcher_input_state);
             update_if_changed!(2, self, dir_replacer_input_state);
-            update_if_changed!(3, self, file_matcher_input_state);
-            update_if_changed!(4, self, file_replacer_input_state);
+            update_if_changed!(3, self, offset_input_state);
+            update_if_changed!(4, self, specials_input_state);
+            update_if_changed!(5, self, junk_input_state);
+            update_rule_if_changed!(RULE_MATCHER, self, file_matcher_input_state);
+            update_rule_if_changed!(RULE_REPLACER, self, file_replacer_input_state);
+            update_rule_if_changed!(RULE_FILTER, self, rule_filter_input_state);
+            if self.form.example_input_state.changed() {
+                self.example_error = None;
+            }
+            self.load_output_tree();
             return AppTransition::None;
         }
 
-        match event {
+        let transition = match event {
             Event::Key(key) => self.on_key(key),
             _ => AppTransition::None,
-        }
+        };
+        self.load_output_tree();
+        transition
     }
 }
 
@@ -74,8 +221,12 @@ impl ConfigureMappingState {
                     AppTransition::AbortConfiguration
                 }
             }
+            KeyCode::Enter if self.form.example_input_state.is_focused() => {
+                self.apply_example();
+                AppTransition::None
+            }
             KeyCode::Enter => {
-                AppTransition::CommitConfiguration(self.mapping_idx, self.mapped_dir.clone())
+                AppTransition::ReviewConfiguration(self.mapping_idx, self.mapped_dir.clone())
             }
             KeyCode::BackTab => {
                 self.form.focus_prev_input();
@@ -85,6 +236,49 @@ impl ConfigureMappingState {
                 self.form.focus_next_input();
                 AppTransition::None
             }
+            KeyCode::Up => self.select_file(self.selected_file.saturating_sub(1)),
+            KeyCode::Down => {
+                let num_files = self.mapped_dir.file_mappings().len();
+                self.select_file((self.selected_file + 1).min(num_files.saturating_sub(1)))
+            }
+            KeyCode::Char('[') => self.select_rule(self.selected_rule.saturating_sub(1)),
+            KeyCode::Char(']') => {
+                let last_rule = self.mapped_dir.file_rules().len() - 1;
+                self.select_rule((self.selected_rule + 1).min(last_rule))
+            }
+            KeyCode::Char('a') => {
+                let rule_idx = self.mapped_dir.add_rule(self.selected_rule);
+                self.select_rule(rule_idx)
+            }
+            KeyCode::Char('d') => {
+                let rule_idx = self.mapped_dir.remove_rule(self.selected_rule);
+                self.select_rule(rule_idx)
+            }
+            KeyCode::Char('{') => {
+                let rule_idx = self.mapped_dir.move_rule(self.selected_rule, true);
+                self.select_rule(rule_idx)
+            }
+            KeyCode::Char('}') => {
+                let rule_idx = self.mapped_dir.move_rule(self.selected_rule, false);
+                self.select_rule(rule_idx)
+            }
+            KeyCode::Char('c') => {
+                self.mapped_dir.toggle_rule_chain(self.selected_rule);
+                AppTransition::None
+            }
+            KeyCode::Char('j') => {
+                self.mapped_dir.toggle_junk(self.selected_file);
+                AppTransition::None
+            }
+            KeyCode::Char('m') => {
+                self.mapped_dir.cycle_link_mode();
+                AppTransition::None
+            }
+            KeyCode::Char('k') => {
+                self.mapped_dir.cycle_media_kind();
+                self.load_form();
+                AppTransition::None
+            }
             _ => AppTransition::None,
         }
     }
@@ -0,0 +1,69 @@
+use crossterm::event::{Event, KeyCode, KeyEvent};
+
+use crate::plan::Plan;
+
+use super::{app_transition::AppTransition, mapping_state::MappedDir, AppState};
+
+// Shows the plan for committing a configured mapping, nothing is written until it's confirmed
+pub struct ConfirmPlanState {
+    pub mapping_idx: usize,
+    pub mapped_dir: MappedDir,
+    pub plan: Plan,
+    pub scroll: usize,
+    // the output dir can be shared with other input dirs
+    pub allow_merge: bool,
+}
+
+impl ConfirmPlanState {
+    pub fn new(mapping_idx: usize, mapped_dir: MappedDir, plan: Plan) -> ConfirmPlanState {
+        ConfirmPlanState {
+            mapping_idx,
+            mapped_dir,
+            plan,
+            scroll: 0,
+            allow_merge: false,
+        }
+    }
+
+    pub fn can_apply(&self) -> bool {
+        self.plan.can_apply(self.allow_merge)
+    }
+}
+
+impl AppState for ConfirmPlanState {
+    fn on_event(&mut self, event: Event) -> AppTransition {
+        match event {
+            Event::Key(key) => self.on_key(key),
+            _ => AppTransition::None,
+        }
+    }
+}
+
+impl ConfirmPlanState {
+    fn on_key(&mut self, key: KeyEvent) -> AppTransition {
+        match key.code {
+            KeyCode::Esc | KeyCode::Char('q') | KeyCode::Char('n') => AppTransition::AbortReview,
+            KeyCode::Enter | KeyCode::Char('y') if self.can_apply() => {
+                AppTransition::CommitConfiguration(
+                    self.mapping_idx,
+                    self.mapped_dir.clone(),
+                    self.plan.clone(),
+                )
+            }
+            KeyCode::Char('m') => {
+                self.allow_merge = !self.allow_merge;
+                AppTransition::None
+            }
+            KeyCode::Up => {
+                self.scroll = self.scroll.saturating_sub(1);
+                AppTransition::None
+            }
+            KeyCode::Down => {
+                let num_lines = self.plan.operations().len();
+                self.scroll = (self.scroll + 1).min(num_lines.saturating_sub(1));
+                AppTransition::None
+            }
+            _ => AppTransition::None,
+        }
+    }
+}
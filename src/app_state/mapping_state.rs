@@ -1,111 +1,287 @@
-use regex::Regex;
+use std::collections::{BTreeSet, HashMap, HashSet};
 
 use crate::{
-    path_utils::{file_name, split_ext},
-    renamer::Renamer,
+    file_filter::FileFilter,
+    file_kind::FileKind,
+    junk::{self, JunkList},
+    ledger::{EntryKind, LedgerEntry},
+    link_mode::LinkMode,
+    media_kind::MediaKind,
+    movie,
+    path_utils::{
+        dir_name, file_name, is_inside, join_path, resolve_parents, split_ext, split_ext_tags,
+    },
+    release_name,
+    rename_example::infer_rule,
+    renamer::{process_rules, RenameRule, Renamer},
+    specials::{self, SpecialRules},
+    template::Variables,
 };
 
-pub const NUM_CONFIGS: usize = 5;
-pub const NUM_SERIALIZED: usize = NUM_CONFIGS + 1;
+pub const NUM_CONFIGS: usize = 6;
+// in path, configs, link mode, media kind, kept junk
+pub const NUM_SERIALIZED: usize = NUM_CONFIGS + 4;
+pub const NUM_RULE_CONFIGS: usize = 3;
 
 const CONFIG_FILE_EXT: usize = 0;
 const CONFIG_DIR_MATCHER: usize = 1;
 const CONFIG_DIR_REPLACER: usize = 2;
-const CONFIG_FILE_MATCHER: usize = 3;
-const CONFIG_FILE_REPLACER: usize = 4;
+const CONFIG_OFFSET: usize = 3;
+const CONFIG_SPECIALS: usize = 4;
+const CONFIG_JUNK: usize = 5;
+
+pub const RULE_MATCHER: usize = 0;
+pub const RULE_REPLACER: usize = 1;
+pub const RULE_FILTER: usize = 2;
 
 #[derive(Clone)]
 pub enum FileMapping {
     MappedTo { from_name: String, to_name: String },
     Filtered { name: String },
+    // the file matcher didn't match, nothing will be linked for it
+    Unmatched { name: String },
+    // an older version of the same episode as `by`, e.g. `01` next to `01v2`
+    Superseded { name: String, by: String },
+    // on the junk list, e.g. a sample or release notes, and not kept
+    Junk { name: String },
+}
+
+impl FileMapping {
+    // name of the file in the input dir
+    pub fn name(&self) -> &str {
+        match self {
+            FileMapping::MappedTo { from_name, .. } => from_name,
+            FileMapping::Filtered { name }
+            | FileMapping::Unmatched { name }
+            | FileMapping::Superseded { name, .. }
+            | FileMapping::Junk { name } => name,
+        }
+    }
+}
+
+// One (matcher, replacer, optional filter) step of a directory's file rename pipeline
+#[derive(Clone, PartialEq)]
+pub struct FileRule {
+    configs: [String; NUM_RULE_CONFIGS],
+    chain: bool,
+}
+
+impl FileRule {
+    pub fn new(matcher: &str, replacer: &str, filter: &str, chain: bool) -> FileRule {
+        FileRule {
+            configs: [matcher, replacer, filter].map(ToString::to_string),
+            chain,
+        }
+    }
+
+    pub fn config(&self, idx: usize) -> &str {
+        &self.configs[idx]
+    }
+
+    pub fn chains(&self) -> bool {
+        self.chain
+    }
+
+    fn build(&self, variables: Variables) -> Result<RenameRule, String> {
+        RenameRule::new(
+            &self.configs[RULE_MATCHER],
+            &self.configs[RULE_REPLACER],
+            &self.configs[RULE_FILTER],
+            self.chain,
+        )
+        .map(|rule| rule.with_variables(variables))
+    }
 }
 
 #[derive(Clone)]
 pub struct MappedDir {
     in_dir_path: String,
     configs: [String; NUM_CONFIGS],
+    file_rules: Vec<FileRule>,
+    link_mode: LinkMode,
+    media_kind: MediaKind,
+    // junk files to link anyway, one path per line
+    kept_junk: String,
+    // the dir matcher / replacer the movie suggestion replaced, not persisted
+    dir_configs_before_movie: Option<(String, String)>,
 
     // updated once upon construction
     in_file_list: Vec<String>,
+    // sizes of the files in `in_file_list`, in bytes
+    in_file_sizes: Vec<u64>,
+    // dirs and entries that couldn't be read while scanning, they're left out
+    scan_errors: Vec<String>,
 
     // updated when configs change
     file_mappings: Vec<FileMapping>,
-    file_filter_regex: Option<Regex>,
-    file_renamer: Option<Renamer>,
-    dir_renamer: Option<Renamer>,
+    // output names more than one file maps to
+    duplicate_targets: BTreeSet<String>,
+    file_filter: Result<FileFilter, String>,
+    offset: Option<i64>,
+    special_rules: Result<SpecialRules, String>,
+    junk_list: Result<JunkList, String>,
+    file_renamers: Vec<Result<RenameRule, String>>,
+    dir_renamer: Result<Renamer, String>,
 }
 
 impl MappedDir {
     pub fn configs_eq(&self, other: &Self) -> bool {
         assert!(self.in_dir_path == other.in_dir_path);
         self.configs == other.configs
+            && self.file_rules == other.file_rules
+            && self.link_mode == other.link_mode
+            && self.media_kind == other.media_kind
+            && self.kept_junk == other.kept_junk
     }
 }
 
 impl MappedDir {
-    pub fn deserialize(inputs: [String; NUM_SERIALIZED]) -> MappedDir {
-        let [a, b, c, d, e, f] = inputs;
+    // `ledger` is what the mapping created in the output dir, to find the files it moved there
+    pub fn deserialize(
+        inputs: [String; NUM_SERIALIZED],
+        file_rules: Vec<FileRule>,
+        ledger: &[LedgerEntry],
+    ) -> MappedDir {
+        let [a, b, c, d, e, f, g, h, i, j] = inputs;
         let mut ret = MappedDir {
             in_dir_path: a,
-            configs: [b, c, d, e, f],
+            configs: [b, c, d, e, f, g],
+            file_rules,
+            link_mode: LinkMode::parse(&h).unwrap_or_default(),
+            media_kind: MediaKind::parse(&i).unwrap_or_default(),
+            kept_junk: j,
+            dir_configs_before_movie: None,
             in_file_list: vec![],
+            in_file_sizes: vec![],
+            scan_errors: vec![],
             file_mappings: vec![],
-            file_filter_regex: None,
-            file_renamer: None,
-            dir_renamer: None,
+            duplicate_targets: BTreeSet::new(),
+            file_filter: Ok(FileFilter::default()),
+            offset: None,
+            special_rules: Ok(SpecialRules::default()),
+            junk_list: Ok(JunkList::default()),
+            file_renamers: vec![],
+            dir_renamer: Err(String::new()),
         };
 
-        ret.load_input_file_list();
+        ret.load_input_file_list(ledger);
         ret.configs_changed();
         ret
     }
 
-    fn load_input_file_list(&mut self) {
-        self.in_file_list = std::fs::read_dir(&self.in_dir_path)
-            .unwrap()
-            .filter_map(|path| {
-                let path = path.unwrap();
-                let meta = path.metadata().unwrap();
-                if meta.is_dir() {
-                    return None;
+    // every file under the input dir, as paths relative to it, e.g. `Subs/English.srt`.
+    // Symlinks aren't followed. Files moved into the output dir are listed from the ledger, so
+    // they're still mapped, and can be moved back.
+    fn load_input_file_list(&mut self, ledger: &[LedgerEntry]) {
+        let mut files = vec![];
+        let mut rel_dir_paths = vec![String::new()];
+        while let Some(rel_dir_path) = rel_dir_paths.pop() {
+            let dir_path = if rel_dir_path.is_empty() {
+                self.in_dir_path.clone()
+            } else {
+                join_path(&self.in_dir_path, &rel_dir_path)
+            };
+            let entries = match std::fs::read_dir(&dir_path) {
+                Ok(entries) => entries,
+                Err(e) => {
+                    self.scan_errors
+                        .push(format!("can't read `{}`: {}", dir_path, e));
+                    continue;
                 }
-                if meta.is_symlink() {
-                    return None;
+            };
+            for entry in entries {
+                let entry = match entry {
+                    Ok(entry) => entry,
+                    Err(e) => {
+                        self.scan_errors
+                            .push(format!("can't read `{}`: {}", dir_path, e));
+                        continue;
+                    }
+                };
+                let name = entry.file_name().to_string_lossy().to_string();
+                let rel_path = if rel_dir_path.is_empty() {
+                    name
+                } else {
+                    join_path(&rel_dir_path, &name)
+                };
+                let file_type = match entry.file_type() {
+                    Ok(file_type) => file_type,
+                    Err(e) => {
+                        self.scan_errors
+                            .push(format!("can't read `{}`: {}", rel_path, e));
+                        continue;
+                    }
+                };
+                if file_type.is_dir() {
+                    rel_dir_paths.push(rel_path);
+                } else if file_type.is_file() {
+                    files.push((rel_path, entry.metadata().map_or(0, |meta| meta.len())));
                 }
-                Some(path.file_name().to_string_lossy().to_string())
-            })
-            .collect();
-        self.in_file_list.sort();
+            }
+        }
+        let in_dir_prefix = format!("{}/", self.in_dir_path);
+        for entry in ledger {
+            let rel_path = match entry.source_path.strip_prefix(&in_dir_prefix) {
+                Some(rel_path) if entry.kind == EntryKind::Link(LinkMode::Move) => rel_path,
+                _ => continue,
+            };
+            if !files.iter().any(|(path, _)| path == rel_path) {
+                let size = std::fs::metadata(&entry.out_path).map_or(0, |meta| meta.len());
+                files.push((rel_path.to_string(), size));
+            }
+        }
+        files.sort();
+        (self.in_file_list, self.in_file_sizes) = files.into_iter().unzip();
     }
 
     fn configs_changed(&mut self) {
-        self.file_filter_regex = build_file_filter_regex(self.configs[CONFIG_FILE_EXT].as_str());
-        self.file_renamer = Renamer::new(
-            self.configs[CONFIG_FILE_MATCHER].as_str(),
-            self.configs[CONFIG_FILE_REPLACER].as_str(),
-        );
+        self.file_filter = FileFilter::parse(&self.configs[CONFIG_FILE_EXT]);
+        self.offset = self.configs[CONFIG_OFFSET].trim().parse().ok();
+        self.special_rules = SpecialRules::parse(&self.configs[CONFIG_SPECIALS]);
+        self.junk_list = JunkList::parse(&self.configs[CONFIG_JUNK]);
+
+        let variables = Variables {
+            offset: self.offset.unwrap_or(0),
+        };
+        self.file_renamers = self
+            .file_rules
+            .iter()
+            .map(|rule| rule.build(variables))
+            .collect();
         self.dir_renamer = Renamer::new(
             self.configs[CONFIG_DIR_MATCHER].as_str(),
             self.configs[CONFIG_DIR_REPLACER].as_str(),
-        );
+        )
+        .map(|renamer| renamer.with_variables(variables));
+
+        // files are only renamed when every rule is valid
+        let file_renamers = self
+            .file_renamers
+            .iter()
+            .cloned()
+            .collect::<Result<Vec<_>, _>>()
+            .ok();
 
         self.file_mappings = self
             .in_file_list
             .iter()
-            .map(|path| {
+            .zip(self.in_file_sizes.iter())
+            .map(|(path, size)| {
                 let path = path.clone();
+                // rules see the stem, the whole suffix (e.g. `en.forced.srt`) is put back after
                 let (basename, ext) = split_ext(path.as_str());
 
-                if let Some(ext_matcher) = &self.file_filter_regex {
-                    if let Some(ext) = ext {
-                        if !ext_matcher.is_match(ext) {
-                            return FileMapping::Filtered { name: path.clone() };
-                        }
+                if let Ok(file_filter) = &self.file_filter {
+                    if !file_filter.matches(&path, *size) {
+                        return FileMapping::Filtered { name: path.clone() };
                     }
                 }
 
-                if let Some(file_renamer) = &self.file_renamer {
-                    let renamed = file_renamer.process(basename);
+                if let Some(file_renamers) = &file_renamers {
+                    let renamed = match process_rules(file_renamers, basename) {
+                        Some(renamed) => renamed,
+                        None => return FileMapping::Unmatched { name: path.clone() },
+                    };
 
                     let to_path = if let Some(ext) = ext {
                         format!("{}.{}", renamed, ext)
@@ -125,16 +301,271 @@ impl MappedDir {
                 }
             })
             .collect();
+        self.mark_junk();
+        match self.media_kind {
+            MediaKind::Movie => self.apply_movie_layout(),
+            MediaKind::Tv | MediaKind::Music => self.route_specials(),
+        }
+        self.supersede_versions();
+        self.group_sidecars();
+
+        let mut targets = HashSet::new();
+        self.duplicate_targets = self
+            .file_mappings
+            .iter()
+            .filter_map(|mapping| match mapping {
+                FileMapping::MappedTo { to_name, .. } if !targets.insert(to_name) => {
+                    Some(to_name.clone())
+                }
+                _ => None,
+            })
+            .collect();
+    }
+
+    // the file rules are replaced by `movie::layout` for the files that made it past the filter
+    fn apply_movie_layout(&mut self) {
+        let movie_name = match self.out_dir_name() {
+            Some(out_dir_name) => file_name(&out_dir_name).to_string(),
+            None => return,
+        };
+        let (idxs, files): (Vec<_>, Vec<_>) = self
+            .file_mappings
+            .iter()
+            .enumerate()
+            .filter(|(_, mapping)| {
+                !matches!(
+                    mapping,
+                    FileMapping::Filtered { .. } | FileMapping::Junk { .. }
+                )
+            })
+            .map(|(idx, _)| {
+                (
+                    idx,
+                    (self.in_file_list[idx].as_str(), self.in_file_sizes[idx]),
+                )
+            })
+            .unzip();
+
+        for (idx, to_name) in idxs.into_iter().zip(movie::layout(&movie_name, &files)) {
+            if let Some(to_name) = to_name {
+                self.file_mappings[idx] = FileMapping::MappedTo {
+                    from_name: self.in_file_list[idx].clone(),
+                    to_name,
+                };
+            }
+        }
+    }
+
+    // files past the filter that are on the junk list aren't linked, unless kept. Small videos
+    // are only junk for TV and music, where they aren't specials, movie extras are wanted.
+    fn mark_junk(&mut self) {
+        let junk_list = match &self.junk_list {
+            Ok(junk_list) => junk_list,
+            Err(_) => return,
+        };
+        let special_rules = self.special_rules.as_ref().ok();
+        let largest_video = self
+            .in_file_list
+            .iter()
+            .zip(self.in_file_sizes.iter())
+            .filter(|(path, _)| FileKind::of_path(path) == Some(FileKind::Video))
+            .map(|(_, size)| *size)
+            .max();
+
+        for (idx, (path, size)) in self
+            .in_file_list
+            .iter()
+            .zip(self.in_file_sizes.iter())
+            .enumerate()
+        {
+            if matches!(self.file_mappings[idx], FileMapping::Filtered { .. })
+                || self.is_kept_junk(path)
+            {
+                continue;
+            }
+            let is_special = special_rules.is_some_and(|rules| rules.route("", path).is_some());
+            let largest_video =
+                largest_video.filter(|_| self.media_kind != MediaKind::Movie && !is_special);
+            if junk_list.is_junk(path, *size, largest_video) {
+                self.file_mappings[idx] = FileMapping::Junk { name: path.clone() };
+            }
+        }
+    }
+
+    // videos the special rules recognize go to `Season 00` or an extras folder instead, whether
+    // or not the file rules matched them
+    fn route_specials(&mut self) {
+        let (special_rules, out_dir_name) = match (&self.special_rules, self.out_dir_name()) {
+            (Ok(special_rules), Some(out_dir_name)) => (special_rules, out_dir_name),
+            _ => return,
+        };
+        // specials go in the show's root dir, not e.g. the `Show/Season 03` dir being mapped
+        let show = out_dir_name.split('/').next().unwrap_or_default();
+        let to_show_root = "../".repeat(out_dir_name.matches('/').count());
+
+        for (idx, path) in self.in_file_list.iter().enumerate() {
+            if matches!(
+                self.file_mappings[idx],
+                FileMapping::Filtered { .. } | FileMapping::Junk { .. }
+            ) || FileKind::of_path(path) != Some(FileKind::Video)
+            {
+                continue;
+            }
+            if let Some(to_name) = special_rules.route(show, path) {
+                self.file_mappings[idx] = FileMapping::MappedTo {
+                    from_name: path.clone(),
+                    to_name: to_show_root.clone() + &to_name,
+                };
+            }
+        }
+    }
+
+    // files mapped to the same name once version suffixes are ignored, e.g. `Show - 01.mkv`
+    // and `Show - 01v2.mkv`, only keep the highest version of the input files
+    fn supersede_versions(&mut self) {
+        let mut versions: HashMap<String, Vec<(u32, usize)>> = HashMap::new();
+        for (idx, mapping) in self.file_mappings.iter().enumerate() {
+            if let FileMapping::MappedTo { from_name, to_name } = mapping {
+                let (_, version) = release_name::split_version(file_name(from_name));
+                let (to_name, _) = release_name::split_version(to_name);
+                versions.entry(to_name).or_default().push((version, idx));
+            }
+        }
+
+        for mut group in versions.into_values() {
+            // highest version first, and the earliest file among equals
+            group.sort_by_key(|(version, idx)| (std::cmp::Reverse(*version), *idx));
+            let (latest_version, latest_idx) = group[0];
+            let by = self.in_file_list[latest_idx].clone();
+            for (version, idx) in group.into_iter().skip(1) {
+                // same version, left for duplicate detection
+                if version == latest_version {
+                    continue;
+                }
+                self.file_mappings[idx] = FileMapping::Superseded {
+                    name: self.in_file_list[idx].clone(),
+                    by: by.clone(),
+                };
+            }
+        }
+    }
+
+    // sidecars named like a video follow it, whatever the filter includes and the rules say
+    // about them, so `Ep.en.srt` next to `Ep.mkv` becomes `Show - S01E01.en.srt` in the video's
+    // dir. A video in the sidecar's own dir is preferred, e.g. for `Season 2/01.srt`, otherwise
+    // the name must be unique. Junk, including filtered sidecars the junk list names, and files
+    // a `!` filter term excludes stay as they are.
+    fn group_sidecars(&mut self) {
+        let mut videos: HashMap<&str, Vec<usize>> = HashMap::new();
+        for (idx, path) in self.in_file_list.iter().enumerate() {
+            if FileKind::of_path(path) == Some(FileKind::Video) {
+                videos
+                    .entry(file_name(split_ext(path).0))
+                    .or_default()
+                    .push(idx);
+            }
+        }
+        let file_filter = self.file_filter.as_ref().ok();
+        let junk_list = self.junk_list.as_ref().ok();
+
+        for (idx, path) in self.in_file_list.iter().enumerate() {
+            let size = self.in_file_sizes[idx];
+            if !FileKind::of_path(path).is_some_and(|kind| kind.is_sidecar())
+                || matches!(self.file_mappings[idx], FileMapping::Junk { .. })
+                || file_filter.is_some_and(|filter| filter.excludes(path, size))
+                || (junk_list.is_some_and(|junk_list| junk_list.is_junk(path, size, None))
+                    && !self.is_kept_junk(path))
+            {
+                continue;
+            }
+            let video = split_ext_tags(path).into_iter().find_map(|(stem, suffix)| {
+                let candidates = videos.get(file_name(stem))?;
+                let same_dir = candidates
+                    .iter()
+                    .find(|video_idx| dir_name(&self.in_file_list[**video_idx]) == dir_name(path));
+                match (same_dir, candidates.as_slice()) {
+                    (Some(video_idx), _) | (None, [video_idx]) => Some((*video_idx, suffix)),
+                    _ => None,
+                }
+            });
+            if let Some((video_idx, suffix)) = video {
+                self.file_mappings[idx] = match &self.file_mappings[video_idx] {
+                    FileMapping::MappedTo { to_name, .. } => FileMapping::MappedTo {
+                        from_name: path.clone(),
+                        to_name: format!("{}.{}", split_ext(to_name).0, suffix),
+                    },
+                    FileMapping::Superseded { by, .. } => FileMapping::Superseded {
+                        name: path.clone(),
+                        by: by.clone(),
+                    },
+                    FileMapping::Junk { .. } => FileMapping::Junk { name: path.clone() },
+                    FileMapping::Unmatched { .. } => FileMapping::Unmatched { name: path.clone() },
+                    FileMapping::Filtered { .. } => FileMapping::Filtered { name: path.clone() },
+                };
+            }
+        }
     }
 
     pub fn has_valid_file_filter(&self) -> bool {
-        self.file_filter_regex.is_some()
+        self.file_filter.is_ok()
     }
     pub fn has_valid_dir_renamer(&self) -> bool {
-        self.dir_renamer.is_some()
+        self.dir_renamer.is_ok()
+    }
+    pub fn has_valid_file_rule(&self, rule_idx: usize) -> bool {
+        matches!(self.file_renamers.get(rule_idx), Some(Ok(_)))
     }
-    pub fn has_valid_file_renamer(&self) -> bool {
-        self.file_renamer.is_some()
+    pub fn has_valid_offset(&self) -> bool {
+        self.offset.is_some()
+    }
+    pub fn has_valid_specials(&self) -> bool {
+        self.special_rules.is_ok()
+    }
+    pub fn has_valid_junk_list(&self) -> bool {
+        self.junk_list.is_ok()
+    }
+
+    // first error found in the file filter, dir renamer or file renamer configs, if any
+    pub fn config_error(&self) -> Option<String> {
+        if let Err(e) = &self.file_filter {
+            return Some(format!("file types: {}", e));
+        }
+        if let Err(e) = &self.special_rules {
+            return Some(format!("specials: {}", e));
+        }
+        if let Err(e) = &self.junk_list {
+            return Some(format!("junk: {}", e));
+        }
+        let out_dir_name = match &self.dir_renamer {
+            Err(e) => return Some(format!("dir: {}", e)),
+            Ok(_) => match self.out_dir_name() {
+                None => return Some("dir: no match".to_string()),
+                Some(name) if !is_inside(&name) => {
+                    return Some(format!("dir: `{}` isn't inside the output dir", name))
+                }
+                Some(name) => name,
+            },
+        };
+        for (idx, renamer) in self.file_renamers.iter().enumerate() {
+            if let Err(e) = renamer {
+                return Some(format!("file rule {}: {}", idx + 1, e));
+            }
+        }
+        for file_mapping in self.file_mappings.iter() {
+            if let FileMapping::MappedTo { to_name, .. } = file_mapping {
+                // routed specials step up into the show's root dir, which is fine
+                if !is_inside(&resolve_parents(&join_path(&out_dir_name, to_name))) {
+                    return Some(format!("files: `{}` isn't inside the output dir", to_name));
+                }
+            }
+        }
+        if let Some(to_name) = self.duplicate_targets.iter().next() {
+            return Some(format!("files: several map to `{}`", to_name));
+        }
+        if self.offset.is_none() {
+            return Some("offset: not an integer".to_string());
+        }
+        None
     }
 
     pub fn serialize(&self) -> [&str; NUM_SERIALIZED] {
@@ -145,9 +576,17 @@ impl MappedDir {
             self.configs[2].as_str(),
             self.configs[3].as_str(),
             self.configs[4].as_str(),
+            self.configs[5].as_str(),
+            self.link_mode.as_str(),
+            self.media_kind.as_str(),
+            self.kept_junk.as_str(),
         ]
     }
 
+    pub fn config(&self, idx: usize) -> &str {
+        &self.configs[idx]
+    }
+
     pub fn set_config(&mut self, idx: usize, new_val: &str) {
         self.configs
             .get_mut(idx)
@@ -155,18 +594,176 @@ impl MappedDir {
             .replace_range(.., new_val);
         self.configs_changed();
     }
+
+    pub fn link_mode(&self) -> LinkMode {
+        self.link_mode
+    }
+
+    pub fn cycle_link_mode(&mut self) {
+        self.link_mode = self.link_mode.next();
+    }
+
+    pub fn is_kept_junk(&self, path: &str) -> bool {
+        self.kept_junk.lines().any(|kept| kept == path)
+    }
+
+    // junk files at `file_idx` are linked after all, and kept junk goes back to being junk
+    pub fn toggle_junk(&mut self, file_idx: usize) {
+        let path = &self.in_file_list[file_idx];
+        if self.is_kept_junk(path) {
+            self.kept_junk = self
+                .kept_junk
+                .lines()
+                .filter(|kept| kept != path)
+                .collect::<Vec<_>>()
+                .join("\n");
+        } else if let FileMapping::Junk { .. } = self.file_mappings[file_idx] {
+            if !self.kept_junk.is_empty() {
+                self.kept_junk.push('\n');
+            }
+            self.kept_junk.push_str(path);
+        } else {
+            return;
+        }
+        self.configs_changed();
+    }
+
+    pub fn media_kind(&self) -> MediaKind {
+        self.media_kind
+    }
+
+    // movies get their output dir named `Title (Year)` when the input dir name has a year.
+    // Cycling past Movie brings the previous dir configs back, unless they were edited since.
+    pub fn cycle_media_kind(&mut self) {
+        let suggestion = release_name::suggest_movie_dir(self.in_dir_name());
+        let dir_configs = |mapped_dir: &MappedDir| {
+            (
+                mapped_dir.configs[CONFIG_DIR_MATCHER].clone(),
+                mapped_dir.configs[CONFIG_DIR_REPLACER].clone(),
+            )
+        };
+        if self.media_kind == MediaKind::Movie {
+            if let Some((matcher, replacer)) = self.dir_configs_before_movie.take() {
+                if Some(dir_configs(self)) == suggestion {
+                    self.configs[CONFIG_DIR_MATCHER] = matcher;
+                    self.configs[CONFIG_DIR_REPLACER] = replacer;
+                }
+            }
+        }
+        self.media_kind = self.media_kind.next();
+        if self.media_kind == MediaKind::Movie {
+            if let Some((matcher, replacer)) = suggestion {
+                self.dir_configs_before_movie = Some(dir_configs(self));
+                self.configs[CONFIG_DIR_MATCHER] = matcher;
+                self.configs[CONFIG_DIR_REPLACER] = replacer;
+            }
+        }
+        self.configs_changed();
+    }
 }
 
-fn build_file_filter_regex(s: &str) -> Option<Regex> {
-    let j = s
-        .to_string()
-        .split(',')
-        .map(regex::escape)
-        .collect::<Vec<_>>()
-        .join("|");
-    match Regex::new(&j) {
-        Ok(r) => Some(r),
-        Err(_) => None,
+impl MappedDir {
+    // prefills the dir matcher / replacer and file rules from the parsed release names, if
+    // the dir and its files are recognized
+    fn apply_release_name_suggestion(&mut self) {
+        let file_basenames: Vec<_> = self
+            .in_file_list
+            .iter()
+            .map(|name| file_name(split_ext(name).0))
+            .collect();
+
+        if let Some(suggestion) = release_name::suggest(self.in_dir_name(), &file_basenames) {
+            self.configs[CONFIG_DIR_MATCHER] = suggestion.dir_matcher;
+            self.configs[CONFIG_DIR_REPLACER] = suggestion.dir_replacer;
+            self.file_rules = vec![FileRule::new(
+                &suggestion.file_matcher,
+                &suggestion.file_replacer,
+                "",
+                false,
+            )];
+            self.configs_changed();
+        }
+    }
+
+    // replaces the rule's matcher / replacer with ones inferred from renaming the file at
+    // `file_idx` to `out_name`
+    pub fn apply_example(
+        &mut self,
+        rule_idx: usize,
+        file_idx: usize,
+        out_name: &str,
+    ) -> Result<(), String> {
+        let (in_name, ext) = split_ext(&self.in_file_list[file_idx]);
+        let out_name = match ext {
+            Some(ext) => out_name
+                .strip_suffix(&format!(".{}", ext))
+                .unwrap_or(out_name),
+            None => out_name,
+        };
+        let siblings: Vec<_> = self
+            .in_file_list
+            .iter()
+            .zip(self.file_mappings.iter())
+            .filter(|(_, mapping)| !matches!(mapping, FileMapping::Filtered { .. }))
+            .map(|(name, _)| split_ext(name).0)
+            .collect();
+
+        let (matcher, replacer) = infer_rule(in_name, out_name, &siblings)?;
+        let rule = &mut self.file_rules[rule_idx];
+        rule.configs[RULE_MATCHER] = matcher;
+        rule.configs[RULE_REPLACER] = replacer;
+        self.configs_changed();
+        Ok(())
+    }
+
+    pub fn file_rules(&self) -> &Vec<FileRule> {
+        &self.file_rules
+    }
+
+    pub fn set_rule_config(&mut self, rule_idx: usize, idx: usize, new_val: &str) {
+        self.file_rules[rule_idx].configs[idx].replace_range(.., new_val);
+        self.configs_changed();
+    }
+
+    pub fn toggle_rule_chain(&mut self, rule_idx: usize) {
+        let rule = &mut self.file_rules[rule_idx];
+        rule.chain = !rule.chain;
+        self.configs_changed();
+    }
+
+    // inserts a copy of the rule at `rule_idx` after it, returns the new rule's index
+    pub fn add_rule(&mut self, rule_idx: usize) -> usize {
+        let rule = self.file_rules[rule_idx].clone();
+        self.file_rules.insert(rule_idx + 1, rule);
+        self.configs_changed();
+        rule_idx + 1
+    }
+
+    // removes the rule at `rule_idx` unless it's the last one, returns the index to select next
+    pub fn remove_rule(&mut self, rule_idx: usize) -> usize {
+        if self.file_rules.len() > 1 {
+            self.file_rules.remove(rule_idx);
+            self.configs_changed();
+        }
+        rule_idx.min(self.file_rules.len() - 1)
+    }
+
+    // swaps the rule at `rule_idx` with its neighbour, returns the rule's new index
+    pub fn move_rule(&mut self, rule_idx: usize, up: bool) -> usize {
+        let other_idx = if up {
+            rule_idx.checked_sub(1)
+        } else {
+            Some(rule_idx + 1).filter(|idx| *idx < self.file_rules.len())
+        };
+
+        match other_idx {
+            Some(other_idx) => {
+                self.file_rules.swap(rule_idx, other_idx);
+                self.configs_changed();
+                other_idx
+            }
+            None => rule_idx,
+        }
     }
 }
 
@@ -174,7 +771,8 @@ impl MappedDir {
     pub fn out_dir_name(&self) -> Option<String> {
         self.dir_renamer
             .as_ref()
-            .map(|renamer| renamer.process(self.in_dir_name()))
+            .ok()
+            .and_then(|renamer| renamer.process(self.in_dir_name()))
     }
 
     pub fn in_dir_name(&self) -> &str {
@@ -188,6 +786,14 @@ impl MappedDir {
     pub fn file_mappings(&self) -> &Vec<FileMapping> {
         &self.file_mappings
     }
+
+    pub fn duplicate_targets(&self) -> &BTreeSet<String> {
+        &self.duplicate_targets
+    }
+
+    pub fn scan_errors(&self) -> &Vec<String> {
+        &self.scan_errors
+    }
 }
 
 pub enum MappingState {
@@ -205,15 +811,367 @@ impl MappingState {
     pub fn to_mapped_dir(&self) -> MappedDir {
         match self {
             MappingState::HasMapping { mapped_dir } => mapped_dir.clone(),
-            #[rustfmt::skip]
-            MappingState::Unmapped { in_path } => MappedDir::deserialize(
-                [
-                    in_path.as_str(),
-                    "avi,mkv,mp4", 
-                    "(.+)", "$1", 
-                    "(.+)", "$1"
-                ].map(ToString::to_string),
-            ),
+            MappingState::Unmapped { in_path } => {
+                #[rustfmt::skip]
+                let mut mapped_dir = MappedDir::deserialize(
+                    [
+                        in_path.as_str(),
+                        "avi,mkv,mp4", 
+                        "(.+)", "$1", 
+                        "0",
+                        specials::DEFAULT_RULES,
+                        junk::DEFAULT_JUNK,
+                        LinkMode::default().as_str(),
+                        MediaKind::default().as_str(),
+                        "",
+                    ].map(ToString::to_string),
+                    vec![FileRule::new("(.+)", "$1", "", false)],
+                    &[],
+                );
+                mapped_dir.apply_release_name_suggestion();
+                mapped_dir
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        ledger::{EntryKind, LedgerEntry},
+        link_mode::LinkMode,
+        test_dir::TestDir,
+    };
+
+    use super::{FileMapping, FileRule, MappedDir, MappingState};
+
+    // the mapping suggested for a new input dir
+    fn unmapped_dir(dir: &TestDir) -> MappedDir {
+        MappingState::Unmapped {
+            in_path: dir.root().to_string_lossy().to_string(),
+        }
+        .to_mapped_dir()
+    }
+
+    fn describe_mappings(mapped_dir: &MappedDir) -> Vec<String> {
+        mapped_dir
+            .file_mappings()
+            .iter()
+            .map(|mapping| match mapping {
+                FileMapping::MappedTo { from_name, to_name } => {
+                    format!("{} -> {}", from_name, to_name)
+                }
+                FileMapping::Junk { name } => format!("{} junk", name),
+                FileMapping::Unmatched { name } => format!("{} unmatched", name),
+                _ => format!("{} filtered", mapping.name()),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn scans_nested_files() {
+        let dir = TestDir::new("scan");
+        for name in [
+            "Show.S01E01.mkv",
+            "Subs/English.srt",
+            "Season.1/Extras/Making Of",
+        ] {
+            dir.write(name, "");
+        }
+        std::os::unix::fs::symlink(dir.path("Subs"), dir.path("Linked")).unwrap();
+
+        let mapped_dir = unmapped_dir(&dir);
+        let names: Vec<_> = mapped_dir
+            .file_mappings()
+            .iter()
+            .map(FileMapping::name)
+            .collect();
+        assert_eq!(
+            vec![
+                "Season.1/Extras/Making Of",
+                "Show.S01E01.mkv",
+                "Subs/English.srt"
+            ],
+            names
+        );
+    }
+
+    #[test]
+    fn skips_unreadable_dirs() {
+        use std::{fs, os::unix::fs::PermissionsExt};
+
+        let dir = TestDir::new("unreadable");
+        dir.write("Show.S01E01.mkv", "");
+        dir.write("Locked/Show.S01E02.mkv", "");
+        fs::set_permissions(dir.path("Locked"), fs::Permissions::from_mode(0o000)).unwrap();
+        let mapped_dir = unmapped_dir(&dir);
+        fs::set_permissions(dir.path("Locked"), fs::Permissions::from_mode(0o755)).unwrap();
+        // the locked dir is skipped, unless running as root which reads it anyway
+        assert!(mapped_dir
+            .file_mappings()
+            .iter()
+            .any(|mapping| mapping.name() == "Show.S01E01.mkv"));
+
+        let mapped_dir = MappingState::Unmapped {
+            in_path: dir.path("Gone"),
+        }
+        .to_mapped_dir();
+        assert!(mapped_dir.file_mappings().is_empty());
+        assert_eq!(1, mapped_dir.scan_errors().len());
+        assert!(mapped_dir.scan_errors()[0].starts_with("can't read "));
+    }
+
+    #[test]
+    fn lists_moved_files() {
+        let dir = TestDir::new("moved");
+        dir.write("in/Show.S01E01.mkv", "");
+        dir.write("out/Show/Show.S01E02.mkv", "");
+        let ledger = [LedgerEntry {
+            out_path: dir.path("out/Show/Show.S01E02.mkv"),
+            in_path: dir.path("in"),
+            source_path: dir.path("in/Show.S01E02.mkv"),
+            kind: EntryKind::Link(LinkMode::Move),
+        }];
+
+        #[rustfmt::skip]
+        let mapped_dir = MappedDir::deserialize(
+            [&dir.path("in"), "mkv", "(.+)", "Show", "0", "", "", "move", "tv", ""]
+                .map(ToString::to_string),
+            vec![FileRule::new("(.+)", "$1", "", false)],
+            &ledger,
+        );
+        let names: Vec<_> = mapped_dir
+            .file_mappings()
+            .iter()
+            .map(FileMapping::name)
+            .collect();
+        assert_eq!(vec!["Show.S01E01.mkv", "Show.S01E02.mkv"], names);
+    }
+
+    #[test]
+    fn supersedes_versions() {
+        let dir = TestDir::new("versions");
+        for name in [
+            "Show - 01.mkv",
+            "Show - 01.en.ass",
+            "Show - 01v2.mkv",
+            "Show - 02v2.mkv",
+            "Show - 02v3.mkv",
+            "Show - 03.mkv",
+        ] {
+            dir.write(name, "");
+        }
+
+        let mut mapped_dir = unmapped_dir(&dir);
+        mapped_dir.set_rule_config(0, 0, r"^Show - (\d+)(?:v\d+)?$");
+        mapped_dir.set_rule_config(0, 1, "Show - S01E$1");
+        let mappings: Vec<_> = mapped_dir
+            .file_mappings()
+            .iter()
+            .map(|mapping| match mapping {
+                FileMapping::MappedTo { from_name, to_name } => {
+                    format!("{} -> {}", from_name, to_name)
+                }
+                FileMapping::Superseded { name, by } => format!("{} superseded by {}", name, by),
+                _ => format!("{} not mapped", mapping.name()),
+            })
+            .collect();
+        assert_eq!(
+            vec![
+                "Show - 01.en.ass superseded by Show - 01v2.mkv",
+                "Show - 01.mkv superseded by Show - 01v2.mkv",
+                "Show - 01v2.mkv -> Show - S01E01.mkv",
+                "Show - 02v2.mkv superseded by Show - 02v3.mkv",
+                "Show - 02v3.mkv -> Show - S01E02.mkv",
+                "Show - 03.mkv -> Show - S01E03.mkv",
+            ],
+            mappings
+        );
+        assert!(mapped_dir.duplicate_targets().is_empty());
+    }
+
+    #[test]
+    fn routes_specials_from_show_root() {
+        let dir = TestDir::new("specials");
+        for name in ["Show - 01.mkv", "Show - SP01.mkv", "Show - NCOP1.mkv"] {
+            dir.write(name, "");
+        }
+
+        let mut mapped_dir = unmapped_dir(&dir);
+        mapped_dir.set_config(1, "(.+)");
+        mapped_dir.set_config(2, "Show/Season 03");
+        mapped_dir.set_rule_config(0, 0, r"^Show - (\d+)$");
+        mapped_dir.set_rule_config(0, 1, "Show - S03E$1");
+        let mappings: Vec<_> = mapped_dir
+            .file_mappings()
+            .iter()
+            .map(|mapping| match mapping {
+                FileMapping::MappedTo { from_name, to_name } => {
+                    format!("{} -> {}", from_name, to_name)
+                }
+                _ => format!("{} not mapped", mapping.name()),
+            })
+            .collect();
+        assert_eq!(
+            vec![
+                "Show - 01.mkv -> Show - S03E01.mkv",
+                "Show - NCOP1.mkv -> ../Featurettes/NCOP 1.mkv",
+                "Show - SP01.mkv -> ../Season 00/Show - S00E01 - SP 01.mkv",
+            ],
+            mappings
+        );
+        assert_eq!(None, mapped_dir.config_error());
+    }
+
+    #[test]
+    fn restores_dir_configs_after_movie() {
+        let dir = TestDir::new("media-kind");
+        dir.write("Some.Movie.2020.1080p/Some.Movie.2020.1080p.mkv", "");
+        let mut mapped_dir = MappingState::Unmapped {
+            in_path: dir.path("Some.Movie.2020.1080p"),
+        }
+        .to_mapped_dir();
+        mapped_dir.set_config(2, "Custom");
+
+        mapped_dir.cycle_media_kind();
+        assert_eq!(
+            Some("Some Movie (2020)".to_string()),
+            mapped_dir.out_dir_name()
+        );
+        mapped_dir.cycle_media_kind();
+        assert_eq!(Some("Custom".to_string()), mapped_dir.out_dir_name());
+
+        // edits made while it was a movie are kept
+        mapped_dir.cycle_media_kind();
+        mapped_dir.cycle_media_kind();
+        mapped_dir.set_config(2, "${title} [${year}]");
+        mapped_dir.cycle_media_kind();
+        assert_eq!(
+            Some("Some.Movie [2020]".to_string()),
+            mapped_dir.out_dir_name()
+        );
+    }
+
+    #[test]
+    fn marks_junk() {
+        let dir = TestDir::new("junk");
+        for (name, size) in [
+            ("Show.S01E01.mkv", 1000),
+            ("Show.S01E01.nfo", 10),
+            ("Show.S01E02.mkv", 1000),
+            ("Show.S01E02.sample.mkv", 100),
+            ("Show.S01E03.mkv", 10),
+            ("RARBG.txt", 10),
+        ] {
+            dir.write(name, "x".repeat(size));
+        }
+
+        let mut mapped_dir = unmapped_dir(&dir);
+        mapped_dir.set_config(0, "@video, txt");
+        let junk = |mapped_dir: &MappedDir| -> Vec<String> {
+            mapped_dir
+                .file_mappings()
+                .iter()
+                .filter(|mapping| matches!(mapping, FileMapping::Junk { .. }))
+                .map(|mapping| mapping.name().to_string())
+                .collect()
+        };
+        assert_eq!(
+            vec!["RARBG.txt", "Show.S01E02.sample.mkv", "Show.S01E03.mkv"],
+            junk(&mapped_dir)
+        );
+        // filtered junk sidecars stay filtered rather than following their video
+        assert!(matches!(
+            &mapped_dir.file_mappings()[2],
+            FileMapping::Filtered { name } if name == "Show.S01E01.nfo"
+        ));
+
+        mapped_dir.toggle_junk(5);
+        assert!(mapped_dir.is_kept_junk("Show.S01E03.mkv"));
+        assert_eq!(
+            vec!["RARBG.txt", "Show.S01E02.sample.mkv"],
+            junk(&mapped_dir)
+        );
+        mapped_dir.toggle_junk(5);
+        assert_eq!(3, junk(&mapped_dir).len());
+
+        // kept junk sidecars follow their video again
+        mapped_dir.set_config(0, "@video, nfo");
+        assert_eq!(
+            vec![
+                "Show.S01E01.nfo",
+                "Show.S01E02.sample.mkv",
+                "Show.S01E03.mkv"
+            ],
+            junk(&mapped_dir)
+        );
+        mapped_dir.toggle_junk(2);
+        assert!(matches!(
+            &mapped_dir.file_mappings()[2],
+            FileMapping::MappedTo { to_name, .. } if to_name == "Show.S01E01.nfo"
+        ));
+    }
+
+    #[test]
+    fn groups_sidecars() {
+        let dir = TestDir::new("sidecars");
+        for name in [
+            "Show.S01E01.mkv",
+            "Show.S01E01.en.forced.srt",
+            "Show.S01E01.nfo",
+            "Show.S01E01.de.srt",
+            "Subs/Show.S01E01.eng.ass",
+            "Show.S01E02.en.srt",
+        ] {
+            dir.write(name, "");
         }
+
+        let mut mapped_dir = unmapped_dir(&dir);
+        mapped_dir.set_config(0, "@video, !*.de.srt");
+        mapped_dir.set_rule_config(0, 0, r"(?:.+/)?(.+)\.S(\d+)E(\d+)$");
+        mapped_dir.set_rule_config(0, 1, "$1 - S$2E$3");
+        assert_eq!(
+            vec![
+                "Show.S01E01.de.srt filtered",
+                "Show.S01E01.en.forced.srt -> Show - S01E01.en.forced.srt",
+                "Show.S01E01.mkv -> Show - S01E01.mkv",
+                "Show.S01E01.nfo filtered",
+                "Show.S01E02.en.srt filtered",
+                "Subs/Show.S01E01.eng.ass -> Show - S01E01.eng.ass",
+            ],
+            describe_mappings(&mapped_dir)
+        );
+    }
+
+    #[test]
+    fn groups_sidecars_by_dir() {
+        let dir = TestDir::new("sidecar-dirs");
+        for name in [
+            "Season 1/01.mkv",
+            "Season 2/01.mkv",
+            "Season 2/01.en.srt",
+            "Subs/01.srt",
+            "Extra.mkv",
+            "Extra.en.srt",
+        ] {
+            dir.write(name, "");
+        }
+
+        let mut mapped_dir = unmapped_dir(&dir);
+        mapped_dir.set_config(0, "@video, @subs");
+        mapped_dir.set_rule_config(0, 0, r"^Season (\d+)/(\d+)$");
+        mapped_dir.set_rule_config(0, 1, "Show - S0$1E$2");
+        // `Subs/01.srt` could go with either season, so it's left to the rules
+        assert_eq!(
+            vec![
+                "Extra.en.srt unmatched",
+                "Extra.mkv unmatched",
+                "Season 1/01.mkv -> Show - S01E01.mkv",
+                "Season 2/01.en.srt -> Show - S02E01.en.srt",
+                "Season 2/01.mkv -> Show - S02E01.mkv",
+                "Subs/01.srt unmatched",
+            ],
+            describe_mappings(&mapped_dir)
+        );
     }
 }
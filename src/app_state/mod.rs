@@ -4,6 +4,7 @@ use self::app_transition::AppTransition;
 
 pub mod app_transition;
 pub mod configure_mapping_state;
+pub mod confirm_plan_state;
 pub mod mapping_state;
 pub mod selecting_input_state;
 
@@ -1,11 +1,21 @@
-use std::{cell::RefCell, rc::Rc};
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    fs, io,
+    os::unix::fs::MetadataExt,
+    rc::Rc,
+    sync::mpsc::Receiver,
+};
 
 use crossterm::event::{Event, KeyCode, KeyEvent};
 use tui::widgets::ListState;
 
 use crate::{
-    dao::Dao,
-    path_utils::{compute_prefix, dir_name, join_path},
+    checksum::{self, crc_in_name, format_crc},
+    dao::{db_error, Dao},
+    ledger::{self, EntryKind},
+    path_utils::join_path,
+    plan::Plan,
 };
 
 use super::{
@@ -22,6 +32,17 @@ pub struct SelectingInputState {
     selected_row_idx: usize,
     list_state: RefCell<ListState>,
     logs: Vec<String>,
+    crc_check: Option<CrcCheck>,
+}
+
+// input files being hashed in the background, to compare with the CRC in their names
+struct CrcCheck {
+    in_dir_name: String,
+    // size, mtime and expected CRC of the files not hashed yet, by path
+    pending: HashMap<String, (u64, i64, u32)>,
+    num_checked: usize,
+    num_mismatched: usize,
+    results: Receiver<(String, io::Result<u32>)>,
 }
 
 impl SelectingInputState {
@@ -41,6 +62,7 @@ impl SelectingInputState {
             selected_row_idx: 0,
             list_state: RefCell::new(list_state),
             logs: vec![],
+            crc_check: None,
         };
         ret.update_mappings_cache();
         ret
@@ -74,61 +96,296 @@ impl SelectingInputState {
                     MappingState::Unmapped { in_path }
                 }
             })
+            .collect();
+        let scan_errors: Vec<_> = self
+            .mapping_states
+            .iter()
+            .filter_map(|mapping_state| match mapping_state {
+                MappingState::HasMapping { mapped_dir } => Some(mapped_dir.scan_errors()),
+                MappingState::Unmapped { .. } => None,
+            })
+            .flatten()
+            .cloned()
+            .collect();
+        for scan_error in scan_errors {
+            self.add_log(scan_error);
+        }
+    }
+
+    // files that couldn't be read are left out of the mapping, not fatal
+    pub fn log_scan_errors(&mut self, mapped_dir: &MappedDir) {
+        for scan_error in mapped_dir.scan_errors() {
+            self.add_log(scan_error.clone());
+        }
+    }
+
+    // the operations committing `new_mapped_dir` in place of the mapping at `mapping_idx`
+    // would perform
+    pub fn plan_mapping(&self, mapping_idx: usize, new_mapped_dir: &MappedDir) -> Plan {
+        let old_mapped_dir = match &self.mapping_states[mapping_idx] {
+            MappingState::HasMapping { mapped_dir } => Some(mapped_dir),
+            MappingState::Unmapped { .. } => None,
+        };
+        // this mapping's entries, plus those of every contributor to its old and new out dir
+        let dao = self.dao.borrow();
+        let mut ledger = dao.get_ledger_entries(new_mapped_dir.in_dir_path());
+        let out_dir_names = old_mapped_dir
+            .and_then(MappedDir::out_dir_name)
+            .into_iter()
+            .chain(new_mapped_dir.out_dir_name());
+        for out_dir_name in out_dir_names {
+            for entry in dao.get_ledger_entries_under(&join_path(&self.out_dir_path, &out_dir_name))
+            {
+                if !ledger.contains(&entry) {
+                    ledger.push(entry);
+                }
+            }
+        }
+        let shared_with = match new_mapped_dir.out_dir_name() {
+            Some(out_dir_name) => self.mappings_sharing_out_dir(mapping_idx, &out_dir_name),
+            None => vec![],
+        };
+        Plan::new(
+            &self.out_dir_path,
+            old_mapped_dir,
+            new_mapped_dir,
+            &ledger,
+            &shared_with,
+        )
+    }
+
+    // names of the other mapped input dirs that output to `out_dir_name`
+    pub fn mappings_sharing_out_dir(&self, mapping_idx: usize, out_dir_name: &str) -> Vec<&str> {
+        self.mapping_states
+            .iter()
+            .enumerate()
+            .filter(|(idx, _)| *idx != mapping_idx)
+            .filter_map(|(_, mapping)| match mapping {
+                MappingState::HasMapping { mapped_dir }
+                    if mapped_dir.out_dir_name().as_deref() == Some(out_dir_name) =>
+                {
+                    Some(mapped_dir.in_dir_name())
+                }
+                _ => None,
+            })
             .collect()
     }
 
-    pub fn commit_mapping(&mut self, mapping_idx: usize, new_mapped_dir: MappedDir) {
-        let old_mapping = &self.mapping_states[mapping_idx];
-        if let MappingState::HasMapping { mapped_dir } = old_mapping {
-            if mapped_dir.configs_eq(&new_mapped_dir) {
-                self.add_log(format!("no change for `{}`", mapped_dir.in_dir_name()));
-                return;
+    // problems with what's on disk for the mapping at `mapping_idx`, compared to the ledger
+    pub fn verify_mapping(&self, mapping_idx: usize) -> Vec<ledger::Problem> {
+        let in_path = match &self.mapping_states[mapping_idx] {
+            MappingState::HasMapping { mapped_dir } => mapped_dir.in_dir_path(),
+            MappingState::Unmapped { in_path } => in_path,
+        };
+        // other contributors to its dirs own their files in there too
+        let dao = self.dao.borrow();
+        let mut entries = dao.get_ledger_entries(in_path);
+        let dir_paths: Vec<_> = entries
+            .iter()
+            .filter(|entry| entry.kind == EntryKind::Dir)
+            .map(|entry| entry.out_path.clone())
+            .collect();
+        for dir_path in dir_paths {
+            for entry in dao.get_ledger_entries_under(&dir_path) {
+                if entry.in_path != in_path {
+                    entries.push(entry);
+                }
             }
         }
+        ledger::verify(in_path, &entries)
+    }
 
-        {
-            // remove the directory for the old mapping, if it exists
-            if let MappingState::HasMapping { mapped_dir } = old_mapping {
-                if let Some(out_dir_name) = mapped_dir.out_dir_name() {
-                    let old_out_dir_path = join_path(&self.out_dir_path, &out_dir_name);
-                    self.add_log(format!("delete dir `{}`", &old_out_dir_path));
-                    std::fs::remove_dir_all(old_out_dir_path).unwrap();
+    pub fn commit_mapping(&mut self, mapping_idx: usize, new_mapped_dir: MappedDir, plan: &Plan) {
+        // config edits that don't change any output path are still saved
+        let configs_unchanged = match &self.mapping_states[mapping_idx] {
+            MappingState::HasMapping { mapped_dir } => mapped_dir.configs_eq(&new_mapped_dir),
+            MappingState::Unmapped { .. } => false,
+        };
+        if plan.operations().is_empty() && configs_unchanged {
+            self.add_log(format!("no change for `{}`", new_mapped_dir.in_dir_name()));
+            return;
+        }
+
+        for file_mapping in new_mapped_dir.file_mappings().iter() {
+            match file_mapping {
+                FileMapping::Unmatched { name } => {
+                    self.add_log(format!("skip unmatched file `{}`", name));
+                }
+                FileMapping::Superseded { name, by } => {
+                    self.add_log(format!("skip `{}`, superseded by `{}`", name, by));
                 }
+                FileMapping::Junk { name } => {
+                    self.add_log(format!("skip junk file `{}`", name));
+                }
+                FileMapping::MappedTo { .. } | FileMapping::Filtered { .. } => {}
             }
         }
 
-        {
-            // create the new directory
-            if let Some(out_dir_name) = new_mapped_dir.out_dir_name() {
-                let new_out_dir_path = join_path(&self.out_dir_path, &out_dir_name);
-                self.add_log(format!("create dir `{}`", &new_out_dir_path));
-                std::fs::create_dir(&new_out_dir_path).unwrap();
+        // the ledger and mapping are only saved if every operation succeeded, and the operations
+        // are undone if they can't be saved
+        let dao = self.dao.borrow();
+        let logs = &mut self.logs;
+        let in_dir_path = new_mapped_dir.in_dir_path();
+        let mut applied = false;
+        let result = dao.in_transaction(|| {
+            dao.upsert_mapped_dir(&new_mapped_dir).map_err(db_error)?;
+            plan.apply(|operation| {
+                logs.push(operation.to_string());
+                dao.record_operation(in_dir_path, operation)
+                    .map_err(db_error)
+            })?;
+            applied = true;
+            Ok(())
+        });
+        // only the commit can fail once everything is applied
+        let result = match result {
+            Err(e) if applied => match plan.undo() {
+                Ok(()) => Err(e),
+                Err(undo_error) => Err(format!("{}, then {}", e, undo_error)),
+            },
+            result => result,
+        };
+        drop(dao);
 
-                for file_mapping in new_mapped_dir.file_mappings().iter() {
-                    if let FileMapping::MappedTo { from_name, to_name } = file_mapping {
-                        let in_file_path =
-                            format!("{}/{}", new_mapped_dir.in_dir_path(), &from_name);
+        if let Err(e) = result {
+            self.add_log(format!("error: {}", e));
+            self.add_log(format!("rolled back `{}`", new_mapped_dir.in_dir_name()));
+            return;
+        }
 
-                        let out_file_path = join_path(&new_out_dir_path, to_name);
+        self.mapping_states[mapping_idx] = MappingState::HasMapping {
+            mapped_dir: new_mapped_dir,
+        };
+    }
 
-                        let in_file_rel_path =
-                            compute_prefix(dir_name(&in_file_path), dir_name(&out_file_path))
-                                + from_name;
+    // input files of `mapped_dir` whose last computed CRC isn't the one in their name, as
+    // paths relative to its input dir. Files changed since they were hashed aren't included.
+    pub fn crc_mismatches(&self, mapped_dir: &MappedDir) -> HashSet<String> {
+        let dao = self.dao.borrow();
+        mapped_dir
+            .file_mappings()
+            .iter()
+            .map(FileMapping::name)
+            .filter(|name| {
+                let expected = match crc_in_name(name) {
+                    Some(expected) => expected,
+                    None => return false,
+                };
+                let path = join_path(mapped_dir.in_dir_path(), name);
+                fs::metadata(&path)
+                    .ok()
+                    .and_then(|meta| dao.get_file_crc(&path, meta.len(), meta.mtime()))
+                    .is_some_and(|crc| crc != expected)
+            })
+            .map(ToString::to_string)
+            .collect()
+    }
 
-                        self.add_log(format!("in file:  {}", in_file_path));
-                        self.add_log(format!("out file: {}", out_file_path));
-                        self.add_log(format!("link:     {}", in_file_rel_path));
+    // hashes the selected input dir's files that have a CRC in their name, in the background.
+    // Files hashed before are only checked again once their size or mtime changes.
+    fn start_crc_check(&mut self) -> AppTransition {
+        if let Some(crc_check) = &self.crc_check {
+            self.add_log(format!(
+                "crc check of `{}` is still running",
+                crc_check.in_dir_name
+            ));
+            return AppTransition::None;
+        }
 
-                        std::os::unix::fs::symlink(in_file_rel_path, out_file_path).unwrap();
-                    }
+        let mapped_dir = self.mapping_states[self.selected_row_idx].to_mapped_dir();
+        let mut pending = HashMap::new();
+        let mut cached = vec![];
+        for name in mapped_dir.file_mappings().iter().map(FileMapping::name) {
+            let expected = match crc_in_name(name) {
+                Some(expected) => expected,
+                None => continue,
+            };
+            let path = join_path(mapped_dir.in_dir_path(), name);
+            let meta = match fs::metadata(&path) {
+                Ok(meta) => meta,
+                Err(e) => {
+                    self.add_log(format!("crc error `{}`: {}", path, e));
+                    continue;
+                }
+            };
+            match self
+                .dao
+                .borrow()
+                .get_file_crc(&path, meta.len(), meta.mtime())
+            {
+                Some(crc) => cached.push((path, expected, crc)),
+                None => {
+                    pending.insert(path, (meta.len(), meta.mtime(), expected));
                 }
             }
         }
 
-        self.dao.borrow().upsert_mapped_dir(&new_mapped_dir);
-        self.mapping_states[mapping_idx] = MappingState::HasMapping {
-            mapped_dir: new_mapped_dir,
+        let paths = pending.keys().cloned().collect();
+        let mut crc_check = CrcCheck {
+            in_dir_name: mapped_dir.in_dir_name().to_string(),
+            pending,
+            num_checked: 0,
+            num_mismatched: 0,
+            results: checksum::spawn_crc32(paths),
         };
+
+        self.add_log(format!(
+            "crc check `{}`: {} cached, hashing {}",
+            crc_check.in_dir_name,
+            cached.len(),
+            crc_check.pending.len()
+        ));
+        for (path, expected, crc) in cached {
+            self.check_crc(&mut crc_check, &path, expected, crc);
+        }
+        self.crc_check = Some(crc_check);
+        self.poll_crc_check();
+        AppTransition::None
+    }
+
+    // handles the hashes computed since the last call, returns whether there were any
+    pub fn poll_crc_check(&mut self) -> bool {
+        let mut crc_check = match self.crc_check.take() {
+            Some(crc_check) => crc_check,
+            None => return false,
+        };
+
+        let results: Vec<_> = crc_check.results.try_iter().collect();
+        for (path, crc) in results.iter() {
+            let (size, mtime, expected) = crc_check.pending.remove(path).unwrap();
+            match crc {
+                Ok(crc) => {
+                    self.dao.borrow().upsert_file_crc(path, size, mtime, *crc);
+                    self.check_crc(&mut crc_check, path, expected, *crc);
+                }
+                Err(e) => self.add_log(format!("crc error `{}`: {}", path, e)),
+            }
+        }
+
+        if crc_check.pending.is_empty() {
+            self.add_log(format!(
+                "crc check `{}` done: {} ok, {} mismatched",
+                crc_check.in_dir_name,
+                crc_check.num_checked - crc_check.num_mismatched,
+                crc_check.num_mismatched
+            ));
+        } else {
+            self.crc_check = Some(crc_check);
+        }
+        !results.is_empty()
+    }
+
+    fn check_crc(&mut self, crc_check: &mut CrcCheck, path: &str, expected: u32, crc: u32) {
+        crc_check.num_checked += 1;
+        if crc != expected {
+            crc_check.num_mismatched += 1;
+            self.add_log(format!(
+                "crc mismatch `{}`: {} in name, {} computed",
+                path,
+                format_crc(expected),
+                format_crc(crc)
+            ));
+        }
     }
 
     pub fn mappings(&self) -> &Vec<MappingState> {
@@ -158,12 +415,30 @@ impl SelectingInputState {
         match key.code {
             KeyCode::Char('q') => AppTransition::Quit,
             KeyCode::Enter => AppTransition::StartConfiguringIdx(self.selected_row_idx),
+            KeyCode::Char('v') => self.log_verify(),
+            KeyCode::Char('c') => self.start_crc_check(),
             KeyCode::Up => self.select_prev(),
             KeyCode::Down => self.select_next(),
             _ => AppTransition::None,
         }
     }
 
+    fn log_verify(&mut self) -> AppTransition {
+        let in_dir_name = self.mapping_states[self.selected_row_idx]
+            .in_dir_name()
+            .to_string();
+        let problems = self.verify_mapping(self.selected_row_idx);
+        self.add_log(format!(
+            "verify `{}`: {} problems",
+            in_dir_name,
+            problems.len()
+        ));
+        for problem in problems {
+            self.add_log(problem.to_string());
+        }
+        AppTransition::None
+    }
+
     fn select_prev(&mut self) -> AppTransition {
         if self.selected_row_idx > 0 {
             self.selected_row_idx -= 1;
@@ -184,3 +459,43 @@ impl SelectingInputState {
         AppTransition::None
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::{cell::RefCell, rc::Rc};
+
+    use crate::{dao::Dao, media_kind::MediaKind, test_dir::TestDir};
+
+    use super::SelectingInputState;
+
+    #[test]
+    fn saves_config_only_changes() {
+        let dir = TestDir::new("commit");
+        dir.write("in/Show/Show - 01.mkv", "");
+        dir.create_dir("out");
+        let dao = Rc::new(RefCell::new(Dao::new(&dir.path("db.sqlite"))));
+        let mut state = SelectingInputState::new(dao.clone(), dir.path("in"), dir.path("out"));
+
+        let mapped_dir = state.mappings()[0].to_mapped_dir();
+        let plan = state.plan_mapping(0, &mapped_dir);
+        state.commit_mapping(0, mapped_dir.clone(), &plan);
+
+        // music files are laid out like episodes, nothing on disk changes
+        let mut new = mapped_dir;
+        new.cycle_media_kind();
+        new.cycle_media_kind();
+        let plan = state.plan_mapping(0, &new);
+        assert!(plan.operations().is_empty());
+        state.commit_mapping(0, new, &plan);
+
+        let saved = dao
+            .borrow()
+            .get_mapped_dir_by_in_path(&dir.path("in/Show"))
+            .unwrap();
+        assert_eq!(MediaKind::Music, saved.media_kind());
+        assert_eq!(
+            MediaKind::Music,
+            state.mappings()[0].to_mapped_dir().media_kind()
+        );
+    }
+}
@@ -0,0 +1,114 @@
+use std::{
+    fs::File,
+    io::{self, Read},
+    sync::mpsc::{self, Receiver},
+    thread,
+};
+
+use crate::path_utils::{file_name, split_ext};
+
+// CRC-32 (IEEE), the checksum release groups tag their files with, e.g. `[0211A952]`
+const POLYNOMIAL: u32 = 0xEDB8_8320;
+const TABLE: [u32; 256] = crc_table();
+
+const fn crc_table() -> [u32; 256] {
+    let mut table = [0; 256];
+    let mut idx = 0;
+    while idx < 256 {
+        let mut crc = idx as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 {
+                POLYNOMIAL ^ (crc >> 1)
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[idx] = crc;
+        idx += 1;
+    }
+    table
+}
+
+pub fn crc32(mut reader: impl Read) -> io::Result<u32> {
+    let mut buf = vec![0; 1 << 16];
+    let mut crc = !0u32;
+    loop {
+        let len = match reader.read(&mut buf) {
+            Ok(0) => return Ok(!crc),
+            Ok(len) => len,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        for byte in &buf[..len] {
+            crc = TABLE[((crc ^ *byte as u32) & 0xFF) as usize] ^ (crc >> 8);
+        }
+    }
+}
+
+// the checksum in the last `[XXXXXXXX]` or `(XXXXXXXX)` tag of the file name at `path`
+pub fn crc_in_name(path: &str) -> Option<u32> {
+    file_name(split_ext(path).0)
+        .split(['[', '(', ']', ')'])
+        .skip(1)
+        .step_by(2)
+        .filter(|tag| tag.len() == 8 && tag.chars().all(|c| c.is_ascii_hexdigit()))
+        .last()
+        .and_then(|tag| u32::from_str_radix(tag, 16).ok())
+}
+
+pub fn format_crc(crc: u32) -> String {
+    format!("{:08X}", crc)
+}
+
+// hashes the files at `paths` one after another on a background thread, sending each result
+// as soon as it's done. The thread stops early if the receiver is dropped.
+pub fn spawn_crc32(paths: Vec<String>) -> Receiver<(String, io::Result<u32>)> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        for path in paths {
+            let crc = File::open(&path).and_then(crc32);
+            if sender.send((path, crc)).is_err() {
+                return;
+            }
+        }
+    });
+    receiver
+}
+
+#[cfg(test)]
+mod test {
+    use crate::test_dir::TestDir;
+
+    use super::{crc32, crc_in_name, format_crc, spawn_crc32};
+
+    #[test]
+    fn computes_crcs() {
+        assert_eq!(0xCBF4_3926, crc32(&b"123456789"[..]).unwrap());
+        assert_eq!(0, crc32(&b""[..]).unwrap());
+        assert_eq!("0211A952", format_crc(0x0211_A952));
+
+        let dir = TestDir::new("crc");
+        dir.write("digits", "123456789");
+        let results: Vec<_> = spawn_crc32(vec![dir.path("digits"), dir.path("missing")])
+            .iter()
+            .map(|(_, crc)| crc.ok())
+            .collect();
+        assert_eq!(vec![Some(0xCBF4_3926), None], results);
+    }
+
+    #[test]
+    fn reads_crcs_in_names() {
+        assert_eq!(
+            Some(0x0211_A952),
+            crc_in_name("[Orphan] Shirokuma Cafe - 11v2 (BD 720p) [0211A952].mkv")
+        );
+        assert_eq!(
+            Some(0xDEAD_BEEF),
+            crc_in_name("Subs/[Group] Show - 01 [1080p] (deadbeef).en.ass")
+        );
+        assert_eq!(None, crc_in_name("[12345678]/Show - 01 [1080p].mkv"));
+        assert_eq!(None, crc_in_name("Show 12345678.mkv"));
+    }
+}
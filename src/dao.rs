@@ -1,6 +1,92 @@
 use rusqlite::{self, params, Connection};
 
-use crate::app_state::mapping_state::MappedDir;
+use crate::{
+    app_state::mapping_state::{
+        FileRule, MappedDir, NUM_SERIALIZED, RULE_FILTER, RULE_MATCHER, RULE_REPLACER,
+    },
+    ledger::{EntryKind, LedgerEntry},
+    plan::Operation,
+};
+
+const MIGRATIONS: &[&str] = &[
+    r"
+    CREATE TABLE IF NOT EXISTS dir_mappings (
+        in_path TEXT PRIMARY KEY,
+        ext_filter TEXT,
+        dir_matcher TEXT,
+        dir_replacer TEXT,
+        file_matcher TEXT,
+        file_replacer TEXT
+    ) WITHOUT ROWID;
+    ",
+    r"
+    ALTER TABLE dir_mappings ADD COLUMN episode_offset TEXT NOT NULL DEFAULT '0';
+    ",
+    // file_matcher / file_replacer in dir_mappings are superseded by file_rules
+    r"
+    CREATE TABLE file_rules (
+        in_path TEXT NOT NULL,
+        position INTEGER NOT NULL,
+        matcher TEXT NOT NULL,
+        replacer TEXT NOT NULL,
+        filter TEXT NOT NULL,
+        chain INTEGER NOT NULL,
+        PRIMARY KEY (in_path, position)
+    ) WITHOUT ROWID;
+    INSERT INTO file_rules
+        SELECT in_path, 0, file_matcher, file_replacer, '', 0 FROM dir_mappings;
+    ",
+    r"
+    ALTER TABLE dir_mappings ADD COLUMN link_mode TEXT NOT NULL DEFAULT 'relative-symlink';
+    ",
+    // everything created in the output dir, keyed by path. `source_path` is empty for dirs.
+    r"
+    CREATE TABLE ledger (
+        out_path TEXT PRIMARY KEY,
+        in_path TEXT NOT NULL,
+        source_path TEXT NOT NULL,
+        kind TEXT NOT NULL,
+        created_at INTEGER NOT NULL DEFAULT (strftime('%s', 'now'))
+    ) WITHOUT ROWID;
+    CREATE INDEX ledger_in_path ON ledger (in_path);
+    ",
+    // output dirs shared by several input dirs have an entry per contributor
+    r"
+    CREATE TABLE ledger_shared (
+        out_path TEXT NOT NULL,
+        in_path TEXT NOT NULL,
+        source_path TEXT NOT NULL,
+        kind TEXT NOT NULL,
+        created_at INTEGER NOT NULL DEFAULT (strftime('%s', 'now')),
+        PRIMARY KEY (out_path, in_path)
+    ) WITHOUT ROWID;
+    INSERT INTO ledger_shared SELECT out_path, in_path, source_path, kind, created_at FROM ledger;
+    DROP TABLE ledger;
+    ALTER TABLE ledger_shared RENAME TO ledger;
+    CREATE INDEX ledger_in_path ON ledger (in_path);
+    ",
+    r"
+    ALTER TABLE dir_mappings ADD COLUMN media_kind TEXT NOT NULL DEFAULT 'tv';
+    ",
+    // existing mappings keep their specials among the regular episodes
+    r"
+    ALTER TABLE dir_mappings ADD COLUMN specials TEXT NOT NULL DEFAULT '';
+    ",
+    // checksums of input files, only valid while their size and mtime are unchanged
+    r"
+    CREATE TABLE file_crcs (
+        path TEXT PRIMARY KEY,
+        size INTEGER NOT NULL,
+        mtime INTEGER NOT NULL,
+        crc INTEGER NOT NULL
+    ) WITHOUT ROWID;
+    ",
+    // existing mappings keep linking everything past their filter
+    r"
+    ALTER TABLE dir_mappings ADD COLUMN junk TEXT NOT NULL DEFAULT '';
+    ALTER TABLE dir_mappings ADD COLUMN kept_junk TEXT NOT NULL DEFAULT '';
+    ",
+];
 
 pub struct Dao {
     conn: Connection,
@@ -12,21 +98,16 @@ impl Dao {
         Dao { conn }
     }
 
+    // applies every migration past the db's `user_version`, in order
     fn migrate(conn: &Connection) {
-        conn.execute(
-            r"
-            CREATE TABLE IF NOT EXISTS dir_mappings (
-                in_path TEXT PRIMARY KEY,
-                ext_filter TEXT,
-                dir_matcher TEXT,
-                dir_replacer TEXT,
-                file_matcher TEXT,
-                file_replacer TEXT
-            ) WITHOUT ROWID;
-        ",
-            [],
-        )
-        .unwrap();
+        let version: usize = conn
+            .query_row("PRAGMA user_version", [], |row| row.get(0))
+            .unwrap();
+
+        for (idx, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+            conn.execute_batch(migration).unwrap();
+            conn.pragma_update(None, "user_version", idx + 1).unwrap();
+        }
     }
 
     pub fn get_mapped_dir_by_in_path(&self, in_path: &str) -> Option<MappedDir> {
@@ -39,8 +120,12 @@ impl Dao {
             ext_filter,
             dir_matcher, 
             dir_replacer,
-            file_matcher,
-            file_replacer
+            episode_offset,
+            specials,
+            junk,
+            link_mode,
+            media_kind,
+            kept_junk
         FROM dir_mappings
         WHERE in_path = ?
         LIMIT 1
@@ -50,38 +135,270 @@ impl Dao {
 
         let mut cursor = stmt.query(params![in_path]).unwrap();
         if let Some(row) = cursor.next().unwrap() {
-            let cols: [String; 6] = [
+            let cols: [String; NUM_SERIALIZED] = [
                 row.get(0).unwrap(),
                 row.get(1).unwrap(),
                 row.get(2).unwrap(),
                 row.get(3).unwrap(),
                 row.get(4).unwrap(),
                 row.get(5).unwrap(),
+                row.get(6).unwrap(),
+                row.get(7).unwrap(),
+                row.get(8).unwrap(),
+                row.get(9).unwrap(),
             ];
-            Some(MappedDir::deserialize(cols))
+            Some(MappedDir::deserialize(
+                cols,
+                self.get_file_rules(in_path),
+                &self.get_ledger_entries(in_path),
+            ))
         } else {
             None
         }
     }
 
-    pub fn upsert_mapped_dir(&self, mapped_dir: &MappedDir) {
+    fn get_file_rules(&self, in_path: &str) -> Vec<FileRule> {
         let mut stmt = self
             .conn
             .prepare_cached(
                 r"
+        SELECT
+            matcher,
+            replacer,
+            filter,
+            chain
+        FROM file_rules
+        WHERE in_path = ?
+        ORDER BY position
+        ",
+            )
+            .unwrap();
+
+        stmt.query_map(params![in_path], |row| {
+            let matcher: String = row.get(0)?;
+            let replacer: String = row.get(1)?;
+            let filter: String = row.get(2)?;
+            Ok(FileRule::new(&matcher, &replacer, &filter, row.get(3)?))
+        })
+        .unwrap()
+        .map(Result::unwrap)
+        .collect()
+    }
+
+    // run `in_transaction` so the mapping and its rules are replaced together
+    pub fn upsert_mapped_dir(&self, mapped_dir: &MappedDir) -> rusqlite::Result<()> {
+        let mut stmt = self.conn.prepare_cached(
+            r"
         INSERT OR REPLACE INTO dir_mappings (
             in_path,
             ext_filter,
             dir_matcher, 
             dir_replacer,
-            file_matcher,
-            file_replacer
+            episode_offset,
+            specials,
+            junk,
+            link_mode,
+            media_kind,
+            kept_junk
+        )
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+        ",
+        )?;
+
+        stmt.execute(mapped_dir.serialize())?;
+        self.replace_file_rules(mapped_dir)
+    }
+
+    // runs `f` in a transaction, which is rolled back if it returns an error. Failing to start
+    // or commit the transaction is an error too.
+    pub fn in_transaction<T>(&self, f: impl FnOnce() -> Result<T, String>) -> Result<T, String> {
+        let tx = self.conn.unchecked_transaction().map_err(db_error)?;
+        let ret = f()?;
+        tx.commit().map_err(db_error)?;
+        Ok(ret)
+    }
+
+    fn replace_file_rules(&self, mapped_dir: &MappedDir) -> rusqlite::Result<()> {
+        self.conn.execute(
+            "DELETE FROM file_rules WHERE in_path = ?",
+            params![mapped_dir.in_dir_path()],
+        )?;
+
+        let mut stmt = self.conn.prepare_cached(
+            r"
+        INSERT INTO file_rules (
+            in_path,
+            position,
+            matcher,
+            replacer,
+            filter,
+            chain
         )
         VALUES (?, ?, ?, ?, ?, ?)
+        ",
+        )?;
+
+        for (position, rule) in mapped_dir.file_rules().iter().enumerate() {
+            stmt.execute(params![
+                mapped_dir.in_dir_path(),
+                position,
+                rule.config(RULE_MATCHER),
+                rule.config(RULE_REPLACER),
+                rule.config(RULE_FILTER),
+                rule.chains(),
+            ])?;
+        }
+        Ok(())
+    }
+
+    // ledger entries for everything created for the mapping of `in_path`
+    pub fn get_ledger_entries(&self, in_path: &str) -> Vec<LedgerEntry> {
+        let mut stmt = self
+            .conn
+            .prepare_cached(
+                r"
+        SELECT
+            out_path,
+            in_path,
+            source_path,
+            kind
+        FROM ledger
+        WHERE in_path = ?
+        ORDER BY out_path
         ",
             )
             .unwrap();
 
-        stmt.execute(mapped_dir.serialize()).unwrap();
+        stmt.query_map(params![in_path], ledger_entry_from_row)
+            .unwrap()
+            .map(Result::unwrap)
+            .collect()
     }
+
+    // ledger entries of every mapping for `dir_path` and everything under it
+    pub fn get_ledger_entries_under(&self, dir_path: &str) -> Vec<LedgerEntry> {
+        let mut stmt = self
+            .conn
+            .prepare_cached(
+                r"
+        SELECT
+            out_path,
+            in_path,
+            source_path,
+            kind
+        FROM ledger
+        WHERE out_path = ?1 OR substr(out_path, 1, length(?1) + 1) = ?1 || '/'
+        ORDER BY out_path
+        ",
+            )
+            .unwrap();
+
+        stmt.query_map(params![dir_path], ledger_entry_from_row)
+            .unwrap()
+            .map(Result::unwrap)
+            .collect()
+    }
+
+    // updates the ledger to reflect an applied operation of the mapping of `in_path`
+    pub fn record_operation(&self, in_path: &str, operation: &Operation) -> rusqlite::Result<()> {
+        if let Some(entry) = LedgerEntry::created_by(in_path, operation) {
+            self.conn
+                .prepare_cached(
+                    r"
+        INSERT OR REPLACE INTO ledger (
+            out_path,
+            in_path,
+            source_path,
+            kind
+        )
+        VALUES (?, ?, ?, ?)
+        ",
+                )?
+                .execute(params![
+                    entry.out_path,
+                    entry.in_path,
+                    entry.source_path,
+                    entry.kind.as_str()
+                ])?;
+            return Ok(());
+        }
+
+        match operation {
+            Operation::Unlink {
+                out_file_path: path,
+                ..
+            }
+            | Operation::RemoveDir { path }
+            | Operation::LeaveDir { path } => {
+                self.conn.execute(
+                    "DELETE FROM ledger WHERE out_path = ? AND in_path = ?",
+                    params![path, in_path],
+                )?;
+            }
+            Operation::RenameDir { from_path, to_path } => {
+                // the dir and everything under it
+                self.conn.execute(
+                    r"
+        UPDATE ledger
+        SET out_path = ?2 || substr(out_path, length(?1) + 1)
+        WHERE in_path = ?3
+            AND (out_path = ?1 OR substr(out_path, 1, length(?1) + 1) = ?1 || '/')
+        ",
+                    params![from_path, to_path, in_path],
+                )?;
+            }
+            Operation::CreateDir { .. } | Operation::Link { .. } | Operation::JoinDir { .. } => {}
+        }
+        Ok(())
+    }
+
+    // the cached checksum of the file at `path`, if it hasn't changed since it was hashed
+    pub fn get_file_crc(&self, path: &str, size: u64, mtime: i64) -> Option<u32> {
+        let mut stmt = self
+            .conn
+            .prepare_cached(
+                r"
+        SELECT crc
+        FROM file_crcs
+        WHERE path = ? AND size = ? AND mtime = ?
+        ",
+            )
+            .unwrap();
+
+        let mut cursor = stmt.query(params![path, size, mtime]).unwrap();
+        cursor.next().unwrap().map(|row| row.get(0).unwrap())
+    }
+
+    pub fn upsert_file_crc(&self, path: &str, size: u64, mtime: i64, crc: u32) {
+        self.conn
+            .prepare_cached(
+                r"
+        INSERT OR REPLACE INTO file_crcs (
+            path,
+            size,
+            mtime,
+            crc
+        )
+        VALUES (?, ?, ?, ?)
+        ",
+            )
+            .unwrap()
+            .execute(params![path, size, mtime, crc])
+            .unwrap();
+    }
+}
+
+fn ledger_entry_from_row(row: &rusqlite::Row) -> rusqlite::Result<LedgerEntry> {
+    let kind: String = row.get(3)?;
+    Ok(LedgerEntry {
+        out_path: row.get(0)?,
+        in_path: row.get(1)?,
+        source_path: row.get(2)?,
+        kind: EntryKind::parse(&kind).unwrap(),
+    })
+}
+
+// sqlite errors as shown in the log
+pub fn db_error(e: rusqlite::Error) -> String {
+    format!("database: {}", e)
 }
@@ -0,0 +1,175 @@
+use regex::Regex;
+
+use crate::{file_kind::FileKind, path_utils::file_name};
+
+// The "File Types" filter, comma separated terms matched against each input file:
+// - `mkv`, `tar.gz`: the file has exactly that extension
+// - `*.mkv`, `Subs/*`: a glob over the file name, or its path when the glob has a `/`
+// - `@video`, `@subs`, ...: the file is of that kind
+// - `>50MB`, `<2GB`: the file's size is past that threshold
+// Files pass when they match any extension / glob / kind term (or there are none), every size
+// term, and none of the terms prefixed with `!`.
+#[derive(Clone, Debug, Default)]
+pub struct FileFilter {
+    includes: Vec<Term>,
+    excludes: Vec<Term>,
+    sizes: Vec<Term>,
+}
+
+#[derive(Clone, Debug)]
+enum Term {
+    Ext(String),
+    Glob { regex: Regex, whole_path: bool },
+    Kind(FileKind),
+    MinSize(u64),
+    MaxSize(u64),
+}
+
+const GROUPS: &[(&str, FileKind)] = &[
+    ("video", FileKind::Video),
+    ("subs", FileKind::Subtitle),
+    ("audio", FileKind::Audio),
+    ("images", FileKind::Image),
+    ("info", FileKind::Info),
+    ("archives", FileKind::Archive),
+];
+
+impl FileFilter {
+    pub fn parse(s: &str) -> Result<FileFilter, String> {
+        let mut filter = FileFilter::default();
+        for term in s.split(',').map(str::trim).filter(|term| !term.is_empty()) {
+            let (negated, term) = match term.strip_prefix('!') {
+                Some(term) => (true, term.trim_start()),
+                None => (false, term),
+            };
+            let term = Term::parse(term)?;
+            match term {
+                _ if negated => filter.excludes.push(term),
+                Term::MinSize(_) | Term::MaxSize(_) => filter.sizes.push(term),
+                _ => filter.includes.push(term),
+            }
+        }
+        Ok(filter)
+    }
+
+    // `path` is relative to the input dir
+    pub fn matches(&self, path: &str, size: u64) -> bool {
+        (self.includes.is_empty() || self.includes.iter().any(|t| t.matches(path, size)))
+            && self.sizes.iter().all(|term| term.matches(path, size))
+            && !self.excludes(path, size)
+    }
+
+    // whether a `!` term rules the file out, as opposed to it just not being asked for
+    pub fn excludes(&self, path: &str, size: u64) -> bool {
+        self.excludes.iter().any(|term| term.matches(path, size))
+    }
+}
+
+impl Term {
+    fn parse(term: &str) -> Result<Term, String> {
+        if let Some(group) = term.strip_prefix('@') {
+            return GROUPS
+                .iter()
+                .find(|(name, _)| *name == group)
+                .map(|(_, kind)| Term::Kind(*kind))
+                .ok_or_else(|| format!("unknown group `@{}`", group));
+        }
+        if let Some(size) = term.strip_prefix('>') {
+            return parse_size(size).map(Term::MinSize);
+        }
+        if let Some(size) = term.strip_prefix('<') {
+            return parse_size(size).map(Term::MaxSize);
+        }
+        if term.contains(['*', '?']) {
+            let pattern = term
+                .split('*')
+                .map(|part| {
+                    part.split('?')
+                        .map(regex::escape)
+                        .collect::<Vec<_>>()
+                        .join(".")
+                })
+                .collect::<Vec<_>>()
+                .join(".*");
+            let regex = Regex::new(&format!("(?i)^{}$", pattern)).map_err(|e| e.to_string())?;
+            return Ok(Term::Glob {
+                regex,
+                whole_path: term.contains('/'),
+            });
+        }
+        if term.contains(|c: char| c == '/' || c.is_whitespace()) {
+            return Err(format!("`{}` isn't an extension", term));
+        }
+        Ok(Term::Ext(term.trim_start_matches('.').to_ascii_lowercase()))
+    }
+
+    fn matches(&self, path: &str, size: u64) -> bool {
+        match self {
+            // by name rather than `split_ext`, any extension typed in counts, known or not
+            Term::Ext(ext) => {
+                let name = file_name(path).to_ascii_lowercase();
+                name.len() > ext.len() + 1 && name.ends_with(&format!(".{}", ext))
+            }
+            Term::Glob { regex, whole_path } => {
+                regex.is_match(if *whole_path { path } else { file_name(path) })
+            }
+            Term::Kind(kind) => FileKind::of_path(path) == Some(*kind),
+            Term::MinSize(min) => size > *min,
+            Term::MaxSize(max) => size < *max,
+        }
+    }
+}
+
+// e.g. `50MB`, `1.5 GB`, `300k`
+fn parse_size(s: &str) -> Result<u64, String> {
+    let s = s.trim();
+    let unit_start = s
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(s.len());
+    let (num, unit) = s.split_at(unit_start);
+    let num: f64 = num.parse().map_err(|_| format!("`{}` isn't a size", s))?;
+    let multiplier: u64 = match unit.trim().to_ascii_lowercase().trim_end_matches('b') {
+        "" => 1,
+        "k" => 1 << 10,
+        "m" => 1 << 20,
+        "g" => 1 << 30,
+        "t" => 1 << 40,
+        _ => return Err(format!("unknown size unit `{}`", unit)),
+    };
+    Ok((num * multiplier as f64) as u64)
+}
+
+#[cfg(test)]
+mod test {
+    use super::FileFilter;
+
+    const MB: u64 = 1 << 20;
+
+    #[test]
+    fn matches_files() {
+        let filter = FileFilter::parse("mkv, mp4").unwrap();
+        assert!(filter.matches("Ep.mkv", 0));
+        assert!(filter.matches("Subs/Ep.MP4", 0));
+        assert!(!filter.matches("Ep.mkvx", 0));
+        assert!(!filter.matches("mkv", 0));
+        assert!(FileFilter::parse("xyz").unwrap().matches("Ep.xyz", 0));
+
+        let filter = FileFilter::parse("@video,!*sample*,>50MB").unwrap();
+        assert!(filter.matches("Ep.mkv", 100 * MB));
+        assert!(!filter.matches("Ep.mkv", 10 * MB));
+        assert!(!filter.matches("Ep.Sample.mkv", 100 * MB));
+        assert!(!filter.matches("Ep.srt", 100 * MB));
+        assert!(filter.excludes("Ep.Sample.mkv", 100 * MB));
+        assert!(!filter.excludes("Ep.srt", 100 * MB));
+
+        let filter = FileFilter::parse("Subs/*.srt,tar.gz").unwrap();
+        assert!(filter.matches("Subs/English.srt", 0));
+        assert!(!filter.matches("English.srt", 0));
+        assert!(filter.matches("x.tar.gz", 0));
+        assert!(!filter.matches("x.gz", 0));
+
+        assert!(FileFilter::parse("").unwrap().matches("anything", 0));
+        assert!(FileFilter::parse("@movies").is_err());
+        assert!(FileFilter::parse(">50XB").is_err());
+    }
+}
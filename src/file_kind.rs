@@ -0,0 +1,134 @@
+use crate::path_utils::split_ext;
+
+// What a file is, going by its extension
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FileKind {
+    Video,
+    Subtitle,
+    Audio,
+    Image,
+    // `.nfo`s and the like, describing a release
+    Info,
+    Archive,
+}
+
+const REGISTRY: &[(FileKind, &[&str])] = &[
+    (
+        FileKind::Video,
+        &[
+            "avi", "divx", "flv", "m2ts", "m4v", "mkv", "mov", "mp4", "mpeg", "mpg", "ogm", "ts",
+            "webm", "wmv",
+        ],
+    ),
+    (
+        FileKind::Subtitle,
+        &["ass", "idx", "smi", "srt", "ssa", "sub", "sup", "vtt"],
+    ),
+    (
+        FileKind::Audio,
+        &[
+            "aac", "ac3", "dts", "flac", "m4a", "mka", "mp3", "ogg", "opus", "wav",
+        ],
+    ),
+    (
+        FileKind::Image,
+        &["bmp", "gif", "jpeg", "jpg", "png", "tbn", "webp"],
+    ),
+    (FileKind::Info, &["nfo", "sfv", "md5", "txt", "url"]),
+    (
+        FileKind::Archive,
+        &["7z", "bz2", "gz", "r00", "rar", "tar", "xz", "zip", "zst"],
+    ),
+];
+
+// language codes subtitles are tagged with, ISO 639-1 and the 639-2 ones in use
+const LANGUAGES: &[&str] = &[
+    "ar", "ara", "bg", "bul", "ca", "cat", "ces", "chi", "cs", "cze", "da", "dan", "de", "deu",
+    "dut", "el", "ell", "en", "eng", "es", "est", "et", "eu", "fa", "fas", "fi", "fil", "fin",
+    "fr", "fra", "fre", "ger", "gre", "he", "heb", "hin", "hr", "hrv", "hu", "hun", "id", "ind",
+    "it", "ita", "ja", "jpn", "ko", "kor", "lav", "lit", "lt", "lv", "may", "ms", "msa", "nl",
+    "nld", "no", "nob", "nor", "per", "pl", "pol", "por", "pt", "ro", "ron", "ru", "rum", "rus",
+    "sk", "sl", "slk", "slo", "slv", "spa", "sr", "srp", "sv", "swe", "th", "tha", "tr", "tur",
+    "uk", "ukr", "und", "vi", "vie", "zh", "zho",
+];
+
+// flags subtitles are tagged with after their language
+const SUBTITLE_FLAGS: &[&str] = &["cc", "default", "forced", "full", "hi", "sdh", "signs"];
+
+impl FileKind {
+    // e.g. `mkv`, in any case
+    pub fn of_ext(ext: &str) -> Option<FileKind> {
+        let ext = ext.to_ascii_lowercase();
+        REGISTRY
+            .iter()
+            .find(|(_, exts)| exts.contains(&ext.as_str()))
+            .map(|(kind, _)| *kind)
+    }
+
+    // by the last part of the path's extension, e.g. `srt` for `Ep.en.srt`
+    pub fn of_path(path: &str) -> Option<FileKind> {
+        split_ext(path)
+            .1
+            .and_then(|ext| ext.rsplit('.').next())
+            .and_then(FileKind::of_ext)
+    }
+
+    // files of these kinds that share a video's name belong with it
+    pub fn is_sidecar(&self) -> bool {
+        matches!(self, FileKind::Subtitle | FileKind::Image | FileKind::Info)
+    }
+}
+
+// a part before a subtitle's extension that's a language or flag rather than part of the
+// name, e.g. `en`, `pt-BR`, `forced`
+pub fn is_subtitle_tag(tag: &str) -> bool {
+    let tag = tag.to_ascii_lowercase();
+    let (lang, region) = tag.split_once('-').unwrap_or((&tag, ""));
+    SUBTITLE_FLAGS.contains(&tag.as_str())
+        || (LANGUAGES.contains(&lang)
+            && region.len() <= 4
+            && region.chars().all(|c| c.is_ascii_alphanumeric()))
+}
+
+// the part before an archive's extension that belongs to it, e.g. `tar` in `x.tar.gz` or
+// `part1` in `x.part1.rar`
+pub fn is_archive_part(part: &str, ext: &str) -> bool {
+    let part = part.to_ascii_lowercase();
+    match ext.to_ascii_lowercase().as_str() {
+        "gz" | "bz2" | "xz" | "zst" => part == "tar",
+        "rar" => part
+            .strip_prefix("part")
+            .is_some_and(|num| !num.is_empty() && num.chars().all(|c| c.is_ascii_digit())),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{is_archive_part, is_subtitle_tag, FileKind};
+
+    #[test]
+    fn classifies_exts() {
+        assert_eq!(Some(FileKind::Video), FileKind::of_ext("MKV"));
+        assert_eq!(Some(FileKind::Subtitle), FileKind::of_ext("srt"));
+        assert_eq!(None, FileKind::of_ext("S01E01"));
+        assert_eq!(
+            Some(FileKind::Subtitle),
+            FileKind::of_path("Subs/Ep.en.srt")
+        );
+        assert_eq!(Some(FileKind::Archive), FileKind::of_path("Ep.part1.rar"));
+        assert!(FileKind::Info.is_sidecar());
+        assert!(!FileKind::Archive.is_sidecar());
+
+        assert!(is_subtitle_tag("en"));
+        assert!(is_subtitle_tag("pt-BR"));
+        assert!(is_subtitle_tag("SDH"));
+        assert!(!is_subtitle_tag("The"));
+
+        assert!(is_archive_part("tar", "gz"));
+        assert!(is_archive_part("part01", "rar"));
+        assert!(!is_archive_part("part", "rar"));
+        assert!(!is_archive_part("tar", "rar"));
+    }
+}
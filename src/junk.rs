@@ -0,0 +1,92 @@
+use crate::{file_filter::FileFilter, file_kind::FileKind};
+
+// The "Junk" list, comma separated: file type filter terms naming files nobody wants linked,
+// like release notes and samples, and `<N%` for videos under N% of the size of the dir's
+// largest video. `!` terms are never junk, whatever their size. Junk isn't linked unless it's
+// kept by hand.
+pub const DEFAULT_JUNK: &str =
+    "*sample*, *proof*, RARBG*, *.txt, *.nfo, *.url, *.exe, Screens/*, <5%";
+
+#[derive(Clone, Debug, Default)]
+pub struct JunkList {
+    filter: FileFilter,
+    // without any, the filter would match everything that isn't excluded
+    has_name_terms: bool,
+    max_percent: Option<u64>,
+}
+
+impl JunkList {
+    pub fn parse(s: &str) -> Result<JunkList, String> {
+        let mut max_percent = None;
+        let mut terms = vec![];
+        for term in s.split(',').map(str::trim).filter(|term| !term.is_empty()) {
+            match term.strip_prefix('<').and_then(|t| t.strip_suffix('%')) {
+                Some(percent) => {
+                    let percent = percent
+                        .trim()
+                        .parse()
+                        .map_err(|_| format!("`{}` isn't a percentage", term))?;
+                    max_percent = Some(percent);
+                }
+                None => terms.push(term),
+            }
+        }
+        Ok(JunkList {
+            filter: FileFilter::parse(&terms.join(","))?,
+            has_name_terms: terms.iter().any(|term| !term.starts_with('!')),
+            max_percent,
+        })
+    }
+
+    // `largest_video` is the size of the biggest video among the file's siblings, or None
+    // when small videos are wanted, e.g. movie extras
+    pub fn is_junk(&self, path: &str, size: u64, largest_video: Option<u64>) -> bool {
+        if self.filter.excludes(path, size) {
+            return false;
+        }
+        if self.has_name_terms && self.filter.matches(path, size) {
+            return true;
+        }
+        match (self.max_percent, largest_video) {
+            (Some(max_percent), Some(largest_video)) => {
+                FileKind::of_path(path) == Some(FileKind::Video)
+                    && size.saturating_mul(100) < largest_video.saturating_mul(max_percent)
+            }
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{JunkList, DEFAULT_JUNK};
+
+    #[test]
+    fn detects_junk() {
+        const GB: u64 = 1 << 30;
+        let junk_list = JunkList::parse(DEFAULT_JUNK).unwrap();
+        let is_junk = |path| junk_list.is_junk(path, GB, Some(GB));
+        assert!(is_junk("Show.S01E01.sample.mkv"));
+        assert!(is_junk("Sample/show-s01e01-sample.mkv"));
+        assert!(is_junk("RARBG.txt"));
+        assert!(is_junk("RARBG_DO_NOT_MIRROR.exe"));
+        assert!(is_junk("A_TEXT_FILE.txt"));
+        assert!(is_junk("Screens/01.png"));
+        assert!(!is_junk("Show.S01E01.mkv"));
+        assert!(!is_junk("Show.S01E01.en.srt"));
+
+        // small videos, unless they're wanted
+        assert!(junk_list.is_junk("Show.S01E01.mkv", 40 << 20, Some(GB)));
+        assert!(!junk_list.is_junk("Show.S01E01.mkv", 40 << 20, None));
+        assert!(!junk_list.is_junk("Show.S01E01.en.srt", 40 << 20, Some(GB)));
+
+        let only_small = JunkList::parse("<10%, !*.special.mkv").unwrap();
+        assert!(!only_small.is_junk("RARBG.txt", 0, Some(GB)));
+        assert!(only_small.is_junk("Show.S01E01.mkv", 100 << 20, Some(GB)));
+        assert!(!only_small.is_junk("Show.special.mkv", 100 << 20, Some(GB)));
+        let keep_samples = JunkList::parse("*sample*, !*keep*").unwrap();
+        assert!(!keep_samples.is_junk("keep.sample.mkv", GB, Some(GB)));
+        assert!(JunkList::parse("<ten%").is_err());
+        assert!(JunkList::parse("@nope").is_err());
+    }
+}
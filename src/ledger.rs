@@ -0,0 +1,250 @@
+use std::{collections::HashSet, fmt, fs, os::unix::fs::MetadataExt, path::Path};
+
+use crate::{link_mode::LinkMode, plan::Operation};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EntryKind {
+    Dir,
+    Link(LinkMode),
+}
+
+impl EntryKind {
+    // name persisted to the db
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EntryKind::Dir => "dir",
+            EntryKind::Link(mode) => mode.as_str(),
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<EntryKind> {
+        match s {
+            "dir" => Some(EntryKind::Dir),
+            _ => LinkMode::parse(s).map(EntryKind::Link),
+        }
+    }
+}
+
+// Something in the output dir that this tool created. Only ledger entries are ever removed or
+// renamed when a mapping is re-applied.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LedgerEntry {
+    pub out_path: String,
+    // the input dir of the mapping that created it. Shared output dirs have an entry for each.
+    pub in_path: String,
+    // the linked input file, empty for dirs
+    pub source_path: String,
+    pub kind: EntryKind,
+}
+
+impl LedgerEntry {
+    // the entry recorded once `operation` of the mapping of `in_path` is applied, if it
+    // creates anything
+    pub fn created_by(in_path: &str, operation: &Operation) -> Option<LedgerEntry> {
+        match operation {
+            Operation::CreateDir { path } | Operation::JoinDir { path } => Some(LedgerEntry {
+                out_path: path.clone(),
+                in_path: in_path.to_string(),
+                source_path: String::new(),
+                kind: EntryKind::Dir,
+            }),
+            Operation::Link {
+                mode,
+                in_file_path,
+                out_file_path,
+            } => Some(LedgerEntry {
+                out_path: out_file_path.clone(),
+                in_path: in_path.to_string(),
+                source_path: in_file_path.clone(),
+                kind: EntryKind::Link(*mode),
+            }),
+            Operation::Unlink { .. }
+            | Operation::RenameDir { .. }
+            | Operation::RemoveDir { .. }
+            | Operation::LeaveDir { .. } => None,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Problem {
+    // in the ledger, but gone from disk
+    Missing { out_path: String },
+    // in a ledger owned dir, but not created by any of its contributors
+    Foreign { out_path: String },
+    // still there, but no longer what we created
+    Drifted { out_path: String, reason: String },
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Problem::Missing { out_path } => write!(f, "missing `{}`", out_path),
+            Problem::Foreign { out_path } => write!(f, "not ours `{}`", out_path),
+            Problem::Drifted { out_path, reason } => {
+                write!(f, "drifted `{}`: {}", out_path, reason)
+            }
+        }
+    }
+}
+
+// checks the ledger entries of the mapping of `in_path` against what's on disk. `entries` should
+// also have those of other contributors to its dirs, so their files aren't reported as foreign.
+pub fn verify(in_path: &str, entries: &[LedgerEntry]) -> Vec<Problem> {
+    let owned: HashSet<&str> = entries.iter().map(|e| e.out_path.as_str()).collect();
+    let mut problems = vec![];
+
+    for entry in entries.iter().filter(|entry| entry.in_path == in_path) {
+        let out_path = entry.out_path.clone();
+        let out_meta = match fs::symlink_metadata(&entry.out_path) {
+            Ok(meta) => meta,
+            Err(_) => {
+                problems.push(Problem::Missing { out_path });
+                continue;
+            }
+        };
+
+        let mode = match entry.kind {
+            EntryKind::Dir => {
+                if !out_meta.is_dir() {
+                    problems.push(Problem::Drifted {
+                        out_path,
+                        reason: "no longer a dir".to_string(),
+                    });
+                    continue;
+                }
+                let mut children: Vec<_> = fs::read_dir(&entry.out_path)
+                    .into_iter()
+                    .flatten()
+                    .filter_map(Result::ok)
+                    .map(|child| child.path().to_string_lossy().to_string())
+                    .filter(|child| !owned.contains(child.as_str()))
+                    .collect();
+                children.sort();
+                problems.extend(
+                    children
+                        .into_iter()
+                        .map(|out_path| Problem::Foreign { out_path }),
+                );
+                continue;
+            }
+            EntryKind::Link(mode) => mode,
+        };
+
+        if let Some(reason) = link_drift(mode, &entry.source_path, &entry.out_path, &out_meta) {
+            problems.push(Problem::Drifted { out_path, reason });
+        }
+    }
+    problems
+}
+
+fn link_drift(
+    mode: LinkMode,
+    source_path: &str,
+    out_path: &str,
+    out_meta: &fs::Metadata,
+) -> Option<String> {
+    if mode == LinkMode::Move {
+        return None;
+    }
+    let source_meta = match fs::metadata(source_path) {
+        Ok(meta) => meta,
+        Err(_) => return Some(format!("source `{}` is gone", source_path)),
+    };
+
+    match mode {
+        LinkMode::RelativeSymlink | LinkMode::AbsoluteSymlink => {
+            if !out_meta.is_symlink() {
+                return Some("no longer a symlink".to_string());
+            }
+            let target = Path::new(out_path).canonicalize().ok();
+            if target != Path::new(source_path).canonicalize().ok() {
+                return Some("points elsewhere".to_string());
+            }
+        }
+        LinkMode::Hardlink => {
+            if (source_meta.dev(), source_meta.ino()) != (out_meta.dev(), out_meta.ino()) {
+                return Some("no longer a hardlink to the source".to_string());
+            }
+        }
+        LinkMode::Copy => {
+            if source_meta.len() != out_meta.len() {
+                return Some("size differs from the source".to_string());
+            }
+        }
+        LinkMode::Move => {}
+    }
+    None
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+
+    use crate::{link_mode::LinkMode, plan::Operation, test_dir::TestDir};
+
+    use super::{verify, EntryKind, LedgerEntry, Problem};
+
+    #[test]
+    fn round_trips_kinds() {
+        for kind in [EntryKind::Dir, EntryKind::Link(LinkMode::Hardlink)] {
+            assert_eq!(Some(kind), EntryKind::parse(kind.as_str()));
+        }
+    }
+
+    #[test]
+    fn verifies_outputs() {
+        let dir = TestDir::new("ledger");
+        let path = |name: &str| dir.path(name);
+        dir.write("in/a.mkv", "a");
+        dir.write("in/b.mkv", "b");
+
+        let operations = [
+            Operation::CreateDir { path: path("out") },
+            Operation::Link {
+                mode: LinkMode::RelativeSymlink,
+                in_file_path: path("in/a.mkv"),
+                out_file_path: path("out/a.mkv"),
+            },
+            Operation::Link {
+                mode: LinkMode::Hardlink,
+                in_file_path: path("in/b.mkv"),
+                out_file_path: path("out/b.mkv"),
+            },
+            Operation::Link {
+                mode: LinkMode::Copy,
+                in_file_path: path("in/a.mkv"),
+                out_file_path: path("out/c.mkv"),
+            },
+        ];
+        for operation in operations.iter() {
+            operation.apply().unwrap();
+        }
+        let in_path = path("in");
+        let entries: Vec<_> = operations
+            .iter()
+            .filter_map(|operation| LedgerEntry::created_by(&in_path, operation))
+            .collect();
+        assert_eq!(Vec::<Problem>::new(), verify(&in_path, &entries));
+
+        fs::write(path("out/poster.jpg"), "poster").unwrap();
+        fs::remove_file(path("out/a.mkv")).unwrap();
+        fs::remove_file(path("out/b.mkv")).unwrap();
+        fs::write(path("out/b.mkv"), "b").unwrap();
+        assert_eq!(
+            vec![
+                Problem::Foreign {
+                    out_path: path("out/poster.jpg")
+                },
+                Problem::Missing {
+                    out_path: path("out/a.mkv")
+                },
+                Problem::Drifted {
+                    out_path: path("out/b.mkv"),
+                    reason: "no longer a hardlink to the source".to_string()
+                },
+            ],
+            verify(&in_path, &entries)
+        );
+    }
+}
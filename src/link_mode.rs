@@ -0,0 +1,238 @@
+use std::{
+    fs, io,
+    os::unix::{self, fs::MetadataExt},
+};
+
+use crate::path_utils::{compute_prefix, dir_name, file_name};
+
+// How a mapped file is placed into the output dir
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum LinkMode {
+    #[default]
+    RelativeSymlink,
+    AbsoluteSymlink,
+    Hardlink,
+    // a full copy (the kernel may clone it), with the input's mtime so it can be told apart
+    // from files put there by hand
+    Copy,
+    // the input file is moved, and moved back when the mapping is removed
+    Move,
+}
+
+const ALL: [LinkMode; 5] = [
+    LinkMode::RelativeSymlink,
+    LinkMode::AbsoluteSymlink,
+    LinkMode::Hardlink,
+    LinkMode::Copy,
+    LinkMode::Move,
+];
+
+impl LinkMode {
+    // name persisted to the db
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LinkMode::RelativeSymlink => "relative-symlink",
+            LinkMode::AbsoluteSymlink => "absolute-symlink",
+            LinkMode::Hardlink => "hardlink",
+            LinkMode::Copy => "copy",
+            LinkMode::Move => "move",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<LinkMode> {
+        ALL.iter().find(|mode| mode.as_str() == s).copied()
+    }
+
+    pub fn next(&self) -> LinkMode {
+        let idx = ALL.iter().position(|mode| mode == self).unwrap();
+        ALL[(idx + 1) % ALL.len()]
+    }
+
+    // places the file at `in_file_path` at `out_file_path`, returns a description of what
+    // was created for the log
+    pub fn link(&self, in_file_path: &str, out_file_path: &str) -> io::Result<String> {
+        match self {
+            LinkMode::RelativeSymlink => {
+                // resolved first, so the target is right at any depth, whether the paths are
+                // relative or absolute
+                let in_dir_path = fs::canonicalize(dir_name(in_file_path))?;
+                let out_dir_path = fs::canonicalize(dir_name(out_file_path))?;
+                let target = compute_prefix(
+                    &in_dir_path.to_string_lossy(),
+                    &out_dir_path.to_string_lossy(),
+                ) + file_name(in_file_path);
+                unix::fs::symlink(&target, out_file_path)?;
+                Ok(format!("symlink {}", target))
+            }
+            LinkMode::AbsoluteSymlink => {
+                let target = fs::canonicalize(in_file_path)?;
+                unix::fs::symlink(&target, out_file_path)?;
+                Ok(format!("symlink {}", target.to_string_lossy()))
+            }
+            LinkMode::Hardlink => {
+                fs::hard_link(in_file_path, out_file_path)?;
+                Ok(format!("hardlink {}", in_file_path))
+            }
+            LinkMode::Copy => {
+                // `fs::copy` would overwrite, and a partial copy, e.g. once the disk is full,
+                // would be left behind as a file nobody owns
+                if fs::symlink_metadata(out_file_path).is_ok() {
+                    return Err(io::Error::new(
+                        io::ErrorKind::AlreadyExists,
+                        format!("`{}` already exists", out_file_path),
+                    ));
+                }
+                let copied = fs::copy(in_file_path, out_file_path).and_then(|_| {
+                    let modified = fs::metadata(in_file_path)?.modified()?;
+                    fs::File::options()
+                        .write(true)
+                        .open(out_file_path)?
+                        .set_modified(modified)
+                });
+                if let Err(e) = copied {
+                    let _ = fs::remove_file(out_file_path);
+                    return Err(e);
+                }
+                Ok(format!("copy {}", in_file_path))
+            }
+            LinkMode::Move => {
+                fs::rename(in_file_path, out_file_path)?;
+                Ok(format!("move {}", in_file_path))
+            }
+        }
+    }
+
+    // undoes `link`: moved files are moved back, unless something took their place, everything
+    // else is a link or copy of a file still in the input dir and is removed. Fails without
+    // touching anything if the output doesn't look like something `link` created, e.g. a file
+    // someone put there by hand, copies must match the input's size and mtime.
+    pub fn unlink(&self, in_file_path: &str, out_file_path: &str) -> io::Result<()> {
+        if let LinkMode::Move = self {
+            if fs::symlink_metadata(in_file_path).is_ok() {
+                return Err(io::Error::new(
+                    io::ErrorKind::AlreadyExists,
+                    format!("`{}` already exists", in_file_path),
+                ));
+            }
+            return fs::rename(out_file_path, in_file_path);
+        }
+
+        let out_meta = fs::symlink_metadata(out_file_path)?;
+        let is_ours = match self {
+            LinkMode::RelativeSymlink | LinkMode::AbsoluteSymlink => out_meta.is_symlink(),
+            LinkMode::Hardlink => {
+                let in_meta = fs::metadata(in_file_path)?;
+                (in_meta.dev(), in_meta.ino()) == (out_meta.dev(), out_meta.ino())
+            }
+            LinkMode::Copy => {
+                let in_meta = fs::metadata(in_file_path)?;
+                (in_meta.len(), in_meta.modified()?) == (out_meta.len(), out_meta.modified()?)
+            }
+            LinkMode::Move => unreachable!(),
+        };
+        if !is_ours {
+            return Err(io::Error::other(format!(
+                "`{}` wasn't created by a {}",
+                out_file_path,
+                self.as_str()
+            )));
+        }
+        fs::remove_file(out_file_path)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{fs, time::SystemTime};
+
+    use crate::test_dir::TestDir;
+
+    use super::{LinkMode, ALL};
+
+    #[test]
+    fn round_trips_names() {
+        for mode in ALL {
+            assert_eq!(Some(mode), LinkMode::parse(mode.as_str()));
+        }
+        assert_eq!(None, LinkMode::parse("symlink"));
+        assert_eq!(LinkMode::RelativeSymlink, LinkMode::Move.next());
+    }
+
+    #[test]
+    fn links_files() {
+        let dir = TestDir::new("link-mode");
+        dir.write("in/file.mkv", "contents");
+        dir.create_dir("out");
+        let in_file = dir.path("in/file.mkv");
+        let out_dir = dir.root().join("out");
+
+        for mode in ALL {
+            let out_file = out_dir
+                .join(format!("{}.mkv", mode.as_str()))
+                .to_string_lossy()
+                .to_string();
+            mode.link(&in_file, &out_file).unwrap();
+            assert_eq!("contents", fs::read_to_string(&out_file).unwrap());
+            match mode {
+                LinkMode::RelativeSymlink => assert_eq!(
+                    "../in/file.mkv",
+                    fs::read_link(&out_file).unwrap().to_string_lossy()
+                ),
+                LinkMode::AbsoluteSymlink => {
+                    assert!(fs::read_link(&out_file).unwrap().is_absolute())
+                }
+                _ => {}
+            }
+
+            mode.unlink(&in_file, &out_file).unwrap();
+            assert_eq!("contents", fs::read_to_string(&in_file).unwrap());
+            assert!(fs::symlink_metadata(&out_file).is_err());
+        }
+
+        // nested output dirs, reached through a relative path
+        let nested_dir = out_dir.join("Show/Season 01");
+        fs::create_dir_all(&nested_dir).unwrap();
+        let nested_file = nested_dir
+            .join("../Season 01/file.mkv")
+            .to_string_lossy()
+            .to_string();
+        LinkMode::RelativeSymlink
+            .link(&in_file, &nested_file)
+            .unwrap();
+        assert_eq!(
+            "../../../in/file.mkv",
+            fs::read_link(&nested_file).unwrap().to_string_lossy()
+        );
+        assert_eq!("contents", fs::read_to_string(&nested_file).unwrap());
+
+        // files that weren't linked are left alone
+        let foreign_file = out_dir.join("poster.jpg").to_string_lossy().to_string();
+        fs::write(&foreign_file, "poster").unwrap();
+        assert!(LinkMode::RelativeSymlink
+            .unlink(&in_file, &foreign_file)
+            .is_err());
+        assert!(LinkMode::Hardlink.unlink(&in_file, &foreign_file).is_err());
+        assert!(LinkMode::Copy.link(&in_file, &foreign_file).is_err());
+        assert_eq!("poster", fs::read_to_string(&foreign_file).unwrap());
+
+        // a hand-placed file the size of the input isn't taken for a copy
+        let same_size_file = out_dir.join("same.mkv").to_string_lossy().to_string();
+        fs::write(&same_size_file, "CONTENTS").unwrap();
+        fs::File::options()
+            .write(true)
+            .open(&same_size_file)
+            .unwrap()
+            .set_modified(SystemTime::UNIX_EPOCH)
+            .unwrap();
+        assert!(LinkMode::Copy.unlink(&in_file, &same_size_file).is_err());
+        assert_eq!("CONTENTS", fs::read_to_string(&same_size_file).unwrap());
+
+        // moving back doesn't overwrite a file that took the input's place
+        let moved_file = out_dir.join("moved.mkv").to_string_lossy().to_string();
+        LinkMode::Move.link(&in_file, &moved_file).unwrap();
+        fs::write(&in_file, "new").unwrap();
+        assert!(LinkMode::Move.unlink(&in_file, &moved_file).is_err());
+        assert_eq!("new", fs::read_to_string(&in_file).unwrap());
+        assert_eq!("contents", fs::read_to_string(&moved_file).unwrap());
+    }
+}
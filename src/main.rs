@@ -2,6 +2,7 @@ use std::{
     error::Error,
     io::{self, Stdout},
     ops::DerefMut,
+    time::Duration,
 };
 
 use app::{App, AppResult};
@@ -26,11 +27,28 @@ use tui::{
 
 type TTerminal = Terminal<CrosstermBackend<Stdout>>;
 
+const TICK_RATE: Duration = Duration::from_millis(250);
+
 mod app;
 mod app_state;
+mod checksum;
 mod dao;
+mod file_filter;
+mod file_kind;
+mod junk;
+mod ledger;
+mod link_mode;
+mod media_kind;
+mod movie;
 mod path_utils;
+mod plan;
+mod release_name;
+mod rename_example;
 mod renamer;
+mod specials;
+mod template;
+#[cfg(test)]
+mod test_dir;
 mod ui;
 mod widgets;
 
@@ -40,6 +58,18 @@ fn main() -> Result<(), Box<dyn Error>> {
     let out_dir = std::env::args().nth(3).expect("arg 3: out_dir");
     let app = App::new(&db_path, &in_dir, &out_dir);
 
+    match std::env::args().nth(4).as_deref() {
+        Some("--dry-run") => {
+            print_plans(&app);
+            return Ok(());
+        }
+        Some("--verify") => {
+            print_problems(&app);
+            return Ok(());
+        }
+        _ => {}
+    }
+
     // setup terminal
     enable_raw_mode()?;
     let mut stdout = std::io::stdout();
@@ -70,10 +100,14 @@ fn main() -> Result<(), Box<dyn Error>> {
 fn run_app(terminal: &mut TTerminal, mut app: App) -> io::Result<()> {
     loop {
         terminal.draw(|f| ui(f, &app))?;
-        let event = event::read()?;
-        if let AppResult::Quit = app.on_event(event) {
-            return Ok(());
+        // wakes up without input too, so background work shows up as it finishes
+        if event::poll(TICK_RATE)? {
+            let event = event::read()?;
+            if let AppResult::Quit = app.on_event(event) {
+                return Ok(());
+            }
         }
+        app.on_tick();
     }
 }
 
@@ -83,4 +117,26 @@ fn ui<B: Backend>(f: &mut Frame<B>, app: &App) {
     if let Some(state) = &app.configure_mapping_state {
         ui::configure_mapping(f, app, state);
     }
+    if let Some(state) = &app.confirm_plan_state {
+        ui::confirm_plan(f, state);
+    }
+}
+
+// prints what committing each input dir's current (or suggested) mapping would do
+fn print_plans(app: &App) {
+    let selecting_input_state = &app.selecting_input_state;
+    for (idx, mapping) in selecting_input_state.mappings().iter().enumerate() {
+        let plan = selecting_input_state.plan_mapping(idx, &mapping.to_mapped_dir());
+        println!("{}\n{}", mapping.in_dir_name(), plan);
+    }
+}
+
+// prints each mapped input dir's outputs that are missing, not ours, or changed since created
+fn print_problems(app: &App) {
+    let selecting_input_state = &app.selecting_input_state;
+    for (idx, mapping) in selecting_input_state.mappings().iter().enumerate() {
+        for problem in selecting_input_state.verify_mapping(idx) {
+            println!("{}: {}", mapping.in_dir_name(), problem);
+        }
+    }
 }
@@ -0,0 +1,46 @@
+// What an input dir holds, which decides how its files are laid out in the output dir
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum MediaKind {
+    // episodes, renamed by the file rules
+    #[default]
+    Tv,
+    // a main feature named after the output dir, everything else goes to Plex's extras
+    Movie,
+    // tracks, renamed by the file rules like episodes
+    Music,
+}
+
+const ALL: [MediaKind; 3] = [MediaKind::Tv, MediaKind::Movie, MediaKind::Music];
+
+impl MediaKind {
+    // name persisted to the db
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MediaKind::Tv => "tv",
+            MediaKind::Movie => "movie",
+            MediaKind::Music => "music",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<MediaKind> {
+        ALL.iter().find(|kind| kind.as_str() == s).copied()
+    }
+
+    pub fn next(&self) -> MediaKind {
+        let idx = ALL.iter().position(|kind| kind == self).unwrap();
+        ALL[(idx + 1) % ALL.len()]
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{MediaKind, ALL};
+
+    #[test]
+    fn round_trips_names() {
+        for kind in ALL {
+            assert_eq!(Some(kind), MediaKind::parse(kind.as_str()));
+        }
+        assert_eq!(MediaKind::Tv, MediaKind::Music.next());
+    }
+}
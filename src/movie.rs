@@ -0,0 +1,156 @@
+use crate::{
+    file_kind::FileKind,
+    path_utils::{file_name, split_ext},
+};
+
+// Plex extras folders, the words in a file's path that put it there, and the suffix Plex also
+// accepts next to the movie when there's only one of them
+const EXTRAS: &[(&str, &[&str], Option<&str>)] = &[
+    ("Trailers", &["trailer", "teaser"], Some("trailer")),
+    (
+        "Behind The Scenes",
+        &["behind the scenes", "behindthescenes", "making of", "bts"],
+        Some("behindthescenes"),
+    ),
+    ("Deleted Scenes", &["deleted"], None),
+    ("Featurettes", &["featurette", "featurettes"], None),
+    ("Interviews", &["interview", "interviews"], None),
+    ("Shorts", &["short", "shorts"], None),
+    ("Other", &["sample", "extra", "extras", "bonus"], None),
+];
+
+// words in the main feature's name, and the edition they tag it with
+const EDITIONS: &[(&[&str], &str)] = &[
+    (&["directors cut", "director's cut"], "Director's Cut"),
+    (&["extended"], "Extended Edition"),
+    (&["unrated"], "Unrated"),
+    (&["theatrical"], "Theatrical Cut"),
+    (&["ultimate"], "Ultimate Edition"),
+    (&["remastered"], "Remastered"),
+    (&["imax"], "IMAX"),
+    (&["criterion"], "Criterion"),
+];
+
+// output names for a movie release's files, given as (path, size), with `None` for files
+// that aren't videos. The largest video that isn't an extra is the main feature, named after
+// the movie, e.g. `Heat (1995) {edition-Director's Cut}.mkv`. Other videos go to the extras
+// folder their path suggests, or `Other`.
+pub fn layout(movie_name: &str, files: &[(&str, u64)]) -> Vec<Option<String>> {
+    let is_video = |path: &str| FileKind::of_path(path) == Some(FileKind::Video);
+    let main_idx = files
+        .iter()
+        .enumerate()
+        .filter(|(_, (path, _))| is_video(path))
+        .max_by_key(|(_, (path, size))| (extra_folder(path).is_none(), *size))
+        .map(|(idx, _)| idx);
+
+    let mut names: Vec<_> = files.iter().map(|_| None).collect();
+    let mut extras: Vec<(usize, usize)> = vec![];
+    for (idx, (path, _)) in files.iter().enumerate() {
+        if !is_video(path) {
+            continue;
+        }
+        let ext = split_ext(path).1.unwrap_or_default();
+        if Some(idx) == main_idx {
+            let edition = edition_of(path)
+                .map(|edition| format!(" {{edition-{}}}", edition))
+                .unwrap_or_default();
+            names[idx] = Some(format!("{}{}.{}", movie_name, edition, ext));
+        } else {
+            let extra_idx = extra_folder(path).unwrap_or(EXTRAS.len() - 1);
+            extras.push((idx, extra_idx));
+        }
+    }
+
+    for (idx, extra_idx) in extras.iter().copied() {
+        let (folder, _, suffix) = EXTRAS[extra_idx];
+        let (stem, ext) = split_ext(files[idx].0);
+        let ext = ext.unwrap_or_default();
+        let num_of_kind = extras
+            .iter()
+            .filter(|(_, other)| *other == extra_idx)
+            .count();
+        names[idx] = Some(match suffix {
+            Some(suffix) if num_of_kind == 1 => format!("{}-{}.{}", movie_name, suffix, ext),
+            _ => format!("{}/{}.{}", folder, file_name(stem), ext),
+        });
+    }
+    names
+}
+
+// one of the folders Plex looks for extras in
+pub fn is_extras_folder(name: &str) -> bool {
+    name == "Scenes" || EXTRAS.iter().any(|(folder, _, _)| *folder == name)
+}
+
+// index into `EXTRAS` of the folder the words in `path` suggest
+fn extra_folder(path: &str) -> Option<usize> {
+    let words = words_of(path);
+    EXTRAS
+        .iter()
+        .position(|(_, keywords, _)| keywords.iter().any(|k| has_phrase(&words, k)))
+}
+
+fn edition_of(path: &str) -> Option<&'static str> {
+    let words = words_of(split_ext(file_name(path)).0);
+    EDITIONS
+        .iter()
+        .find(|(keywords, _)| keywords.iter().any(|k| has_phrase(&words, k)))
+        .map(|(_, edition)| *edition)
+}
+
+// lowercase words, split on the separators release names use
+fn words_of(s: &str) -> Vec<String> {
+    s.to_lowercase()
+        .split(|c: char| " ._-/()[]{}".contains(c))
+        .filter(|word| !word.is_empty())
+        .map(ToString::to_string)
+        .collect()
+}
+
+fn has_phrase(words: &[String], phrase: &str) -> bool {
+    let phrase: Vec<_> = phrase.split(' ').collect();
+    words
+        .windows(phrase.len())
+        .any(|window| window.iter().zip(phrase.iter()).all(|(a, b)| a == b))
+}
+
+#[cfg(test)]
+mod test {
+    use super::layout;
+
+    const MOVIE: &str = "Heat (1995)";
+
+    #[test]
+    fn lays_out_movies() {
+        let files = [
+            ("Heat.1995.Directors.Cut.1080p.BluRay.x264.mkv", 8000),
+            ("Sample/heat.sample.mkv", 50),
+            ("Extras/Trailer.mkv", 100),
+            ("Extras/Deleted Scene 1.mkv", 200),
+            ("Extras/Deleted Scene 2.mkv", 300),
+            ("Heat.1995.nfo", 1),
+        ];
+        assert_eq!(
+            vec![
+                Some("Heat (1995) {edition-Director's Cut}.mkv".to_string()),
+                Some("Other/heat.sample.mkv".to_string()),
+                Some("Heat (1995)-trailer.mkv".to_string()),
+                Some("Deleted Scenes/Deleted Scene 1.mkv".to_string()),
+                Some("Deleted Scenes/Deleted Scene 2.mkv".to_string()),
+                None,
+            ],
+            layout(MOVIE, &files)
+        );
+
+        // an extra is never the main feature while there's something else
+        let files = [("trailer.mkv", 900), ("heat.mp4", 800)];
+        assert_eq!(
+            vec![
+                Some("Heat (1995)-trailer.mkv".to_string()),
+                Some("Heat (1995).mp4".to_string()),
+            ],
+            layout(MOVIE, &files)
+        );
+    }
+}
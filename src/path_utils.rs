@@ -2,6 +2,8 @@ use std::iter::repeat;
 
 use itertools::Itertools;
 
+use crate::file_kind::{is_archive_part, is_subtitle_tag, FileKind};
+
 pub fn file_name(path: &str) -> &str {
     if let Some(sep) = path.rfind('/') {
         &path[sep + 1..]
@@ -18,14 +20,77 @@ pub fn dir_name(path: &str) -> &str {
     }
 }
 
+// splits off the extension, e.g. `Ep.mkv` -> (`Ep`, `mkv`). Extensions can have several parts:
+// `x.tar.gz`, `x.part1.rar`, and the language / flag tags of subtitles, as in `Ep.en.forced.srt`.
+// A last part that isn't a known extension, as in `Mr. Robot` or `Dr.Who`, isn't.
 pub fn split_ext(path: &str) -> (&str, Option<&str>) {
-    if let Some(sep) = path.rfind('.') {
+    let (mut stem, ext) = match split_last_dot(path) {
+        (stem, Some(ext)) if is_known_ext(ext) => (stem, ext),
+        _ => return (path, None),
+    };
+    match FileKind::of_ext(ext) {
+        Some(FileKind::Subtitle) => {
+            while let (rest, Some(tag)) = split_last_dot(stem) {
+                if rest.is_empty() || !is_subtitle_tag(tag) {
+                    break;
+                }
+                stem = rest;
+            }
+        }
+        Some(FileKind::Archive) => {
+            if let (rest, Some(part)) = split_last_dot(stem) {
+                if !rest.is_empty() && is_archive_part(part, ext) {
+                    stem = rest;
+                }
+            }
+        }
+        _ => {}
+    }
+    (stem, Some(&path[stem.len() + 1..]))
+}
+
+// only the file name has an extension, not e.g. the `Season.1` dir it's in
+fn split_last_dot(path: &str) -> (&str, Option<&str>) {
+    let name_start = path.rfind('/').map_or(0, |sep| sep + 1);
+    if let Some(sep) = path[name_start..].rfind('.').map(|sep| name_start + sep) {
         (&path[0..sep], Some(&path[sep + 1..]))
     } else {
         (path, None)
     }
 }
 
+// extensions of no file kind that still turn up in releases
+const OTHER_EXTS: &[&str] = &[
+    "bat", "cue", "db", "exe", "html", "ini", "iso", "log", "m3u", "par2", "pdf", "sh", "torrent",
+    "xml",
+];
+
+fn is_known_ext(ext: &str) -> bool {
+    FileKind::of_ext(ext).is_some() || OTHER_EXTS.contains(&ext.to_ascii_lowercase().as_str())
+}
+
+// the ways to split `path` into a stem and a suffix of tags ending in the extension, longest
+// stem first, e.g. `Ep.en.forced.srt` gives (`Ep.en.forced`, `srt`), (`Ep.en`, `forced.srt`)
+// and (`Ep`, `en.forced.srt`). Only subtitles have tags, for their language and flags.
+pub fn split_ext_tags(path: &str) -> Vec<(&str, &str)> {
+    let (mut stem, ext) = match split_last_dot(path) {
+        (stem, Some(ext)) => (stem, ext),
+        (_, None) => return vec![],
+    };
+    let mut splits = vec![(stem, ext)];
+    if FileKind::of_ext(ext) != Some(FileKind::Subtitle) {
+        return splits;
+    }
+    while let (rest, Some(tag)) = split_last_dot(stem) {
+        if !is_subtitle_tag(tag) {
+            break;
+        }
+        stem = rest;
+        splits.push((stem, &path[stem.len() + 1..]));
+    }
+    splits
+}
+
 pub fn join_path(mut a: &str, b: &str) -> String {
     if let Some(sep) = a.rfind('/') {
         if sep == a.len() - 1 {
@@ -36,6 +101,28 @@ pub fn join_path(mut a: &str, b: &str) -> String {
     format!("{}/{}", a, b)
 }
 
+// whether `path`, relative to some dir, stays inside it, e.g. `Show/Season 01` does but
+// `../Show`, `/Show` and `Show//Season 01` don't
+pub fn is_inside(path: &str) -> bool {
+    path.split('/')
+        .all(|component| !component.is_empty() && component != "." && component != "..")
+}
+
+// drops the dirs that `..` components step back out of, e.g. `Show/Season 03/../Season 00`
+// -> `Show/Season 00`, without looking at disk. `..`s with nothing to step out of are kept.
+pub fn resolve_parents(path: &str) -> String {
+    let mut components: Vec<&str> = vec![];
+    for component in path.split('/') {
+        match components.last() {
+            Some(&last) if component == ".." && !last.is_empty() && last != ".." => {
+                components.pop();
+            }
+            _ => components.push(component),
+        }
+    }
+    components.join("/")
+}
+
 pub fn compute_prefix_raw(a: &str, b: &str) -> String {
     let a_components = a.split('/').filter(|s| !s.is_empty());
     let b_components = b.split('/').filter(|s| !s.is_empty());
@@ -64,12 +151,45 @@ pub fn compute_prefix(a: &str, b: &str) -> String {
 
 #[cfg(test)]
 mod test {
-    use super::{compute_prefix_raw, join_path, split_ext};
+    use super::{
+        compute_prefix_raw, is_inside, join_path, resolve_parents, split_ext, split_ext_tags,
+    };
 
     #[test]
     fn split_ext_works() {
-        assert_eq!(("foo", Some("bar")), split_ext("foo.bar"));
+        assert_eq!(("foo", Some("pdf")), split_ext("foo.pdf"));
+        assert_eq!(("foo", Some("EXE")), split_ext("foo.EXE"));
+        assert_eq!(("foo.bar", None), split_ext("foo.bar"));
         assert_eq!(("foo", None), split_ext("foo"));
+        assert_eq!(("Ep", Some("en.forced.srt")), split_ext("Ep.en.forced.srt"));
+        assert_eq!(("Ep.The.End", Some("srt")), split_ext("Ep.The.End.srt"));
+        assert_eq!(("archive", Some("tar.gz")), split_ext("archive.tar.gz"));
+        assert_eq!(("name", Some("part1.rar")), split_ext("name.part1.rar"));
+        assert_eq!(("Mr. Robot", None), split_ext("Mr. Robot"));
+        assert_eq!(("Show.S01E01", None), split_ext("Show.S01E01"));
+        assert_eq!(("Dr.Who", None), split_ext("Dr.Who"));
+        assert_eq!(("Vol.2a", None), split_ext("Vol.2a"));
+        assert_eq!(("Season.1/foo", Some("mkv")), split_ext("Season.1/foo.mkv"));
+        assert_eq!(("Season.1/foo", None), split_ext("Season.1/foo"));
+    }
+
+    #[test]
+    fn split_ext_tags_works() {
+        assert_eq!(
+            vec![
+                ("Ep.en.forced", "srt"),
+                ("Ep.en", "forced.srt"),
+                ("Ep", "en.forced.srt")
+            ],
+            split_ext_tags("Ep.en.forced.srt")
+        );
+        assert_eq!(
+            vec![("Ep.pt-BR", "ass"), ("Ep", "pt-BR.ass")],
+            split_ext_tags("Ep.pt-BR.ass")
+        );
+        assert_eq!(vec![("Ep.S01E01", "nfo")], split_ext_tags("Ep.S01E01.nfo"));
+        assert_eq!(vec![("Ep.the", "mkv")], split_ext_tags("Ep.the.mkv"));
+        assert_eq!(Vec::<(&str, &str)>::new(), split_ext_tags("Ep"));
     }
 
     #[test]
@@ -79,6 +199,32 @@ mod test {
         assert_eq!("foo/", join_path("foo/", ""));
     }
 
+    #[test]
+    fn is_inside_works() {
+        assert!(is_inside("foo"));
+        assert!(is_inside("foo/bar baz"));
+        assert!(!is_inside(""));
+        assert!(!is_inside("/foo"));
+        assert!(!is_inside("foo/"));
+        assert!(!is_inside("foo/../../bar"));
+        assert!(!is_inside("./foo"));
+    }
+
+    #[test]
+    fn resolve_parents_works() {
+        assert_eq!(
+            "Show/Season 00/Ep.mkv",
+            resolve_parents("Show/Season 03/../Season 00/Ep.mkv")
+        );
+        assert_eq!(
+            "/out/Show/Ep.mkv",
+            resolve_parents("/out/Show/Season 1/../Ep.mkv")
+        );
+        assert_eq!("../Show", resolve_parents("Show/../../Show"));
+        assert_eq!("/..", resolve_parents("/.."));
+        assert_eq!("Show/Ep.mkv", resolve_parents("Show/Ep.mkv"));
+    }
+
     #[test]
     fn compute_prefix_works() {
         assert_eq!("..", compute_prefix_raw("foo/", "foo/bar"));
@@ -86,5 +232,9 @@ mod test {
         assert_eq!("../baz", compute_prefix_raw("foo/baz", "foo/smaz"));
         assert_eq!("../../whaz/baz", compute_prefix_raw("whaz/baz", "foo/smaz"));
         assert_eq!("../../whaz/baz", compute_prefix_raw("whaz/baz", "foo/smaz"));
+        assert_eq!(
+            "../../../in",
+            compute_prefix_raw("root/in", "root/out/Show/Season 01")
+        );
     }
 }
@@ -0,0 +1,901 @@
+use std::{collections::HashSet, fmt, fs, io, path::Path};
+
+use crate::{
+    app_state::mapping_state::{FileMapping, MappedDir},
+    ledger::{EntryKind, LedgerEntry},
+    link_mode::LinkMode,
+    path_utils::{dir_name, file_name, is_inside, join_path, resolve_parents},
+};
+
+// A single filesystem change made when applying a mapping
+#[derive(Clone, Debug, PartialEq)]
+pub enum Operation {
+    CreateDir {
+        path: String,
+    },
+    Link {
+        mode: LinkMode,
+        in_file_path: String,
+        out_file_path: String,
+    },
+    Unlink {
+        mode: LinkMode,
+        in_file_path: String,
+        out_file_path: String,
+    },
+    RenameDir {
+        from_path: String,
+        to_path: String,
+    },
+    RemoveDir {
+        path: String,
+    },
+    // start or stop contributing to an output dir shared with other input dirs, these only
+    // change the ledger
+    JoinDir {
+        path: String,
+    },
+    LeaveDir {
+        path: String,
+    },
+}
+
+impl Operation {
+    pub fn apply(&self) -> io::Result<()> {
+        match self {
+            Operation::CreateDir { path } => fs::create_dir(path),
+            Operation::Link {
+                mode,
+                in_file_path,
+                out_file_path,
+            } => mode.link(in_file_path, out_file_path).map(|_| ()),
+            Operation::Unlink {
+                mode,
+                in_file_path,
+                out_file_path,
+            } => mode.unlink(in_file_path, out_file_path),
+            Operation::RenameDir { from_path, to_path } => fs::rename(from_path, to_path),
+            Operation::RemoveDir { path } => fs::remove_dir(path),
+            Operation::JoinDir { .. } | Operation::LeaveDir { .. } => Ok(()),
+        }
+    }
+
+    // the operation that undoes this one once it's been applied
+    pub fn inverse(&self) -> Operation {
+        match self.clone() {
+            Operation::CreateDir { path } => Operation::RemoveDir { path },
+            Operation::Link {
+                mode,
+                in_file_path,
+                out_file_path,
+            } => Operation::Unlink {
+                mode,
+                in_file_path,
+                out_file_path,
+            },
+            Operation::Unlink {
+                mode,
+                in_file_path,
+                out_file_path,
+            } => Operation::Link {
+                mode,
+                in_file_path,
+                out_file_path,
+            },
+            Operation::RenameDir { from_path, to_path } => Operation::RenameDir {
+                from_path: to_path,
+                to_path: from_path,
+            },
+            Operation::RemoveDir { path } => Operation::CreateDir { path },
+            Operation::JoinDir { path } => Operation::LeaveDir { path },
+            Operation::LeaveDir { path } => Operation::JoinDir { path },
+        }
+    }
+}
+
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Operation::CreateDir { path } => write!(f, "mkdir  {}", path),
+            Operation::Link {
+                mode,
+                in_file_path,
+                out_file_path,
+            } => write!(
+                f,
+                "link   {} <- {} ({})",
+                out_file_path,
+                in_file_path,
+                mode.as_str()
+            ),
+            Operation::Unlink {
+                mode,
+                out_file_path,
+                ..
+            } => write!(f, "unlink {} ({})", out_file_path, mode.as_str()),
+            Operation::RenameDir { from_path, to_path } => {
+                write!(f, "rename {} -> {}", from_path, to_path)
+            }
+            Operation::RemoveDir { path } => write!(f, "rmdir  {}", path),
+            Operation::JoinDir { path } => write!(f, "join   {}", path),
+            Operation::LeaveDir { path } => write!(f, "leave  {}", path),
+        }
+    }
+}
+
+// The operations needed to go from the old mapping's output to the new one, computed without
+// touching disk. Only links that differ are touched, so anything else in the output dir (e.g.
+// `.plexmatch` files, posters) is left alone. Conflicts are reasons the plan can't be applied.
+#[derive(Clone, Debug, Default)]
+pub struct Plan {
+    operations: Vec<Operation>,
+    conflicts: Vec<String>,
+    // other input dirs mapped to the same output dir, applying needs merging to be allowed
+    shared_with: Vec<String>,
+}
+
+impl Plan {
+    // `ledger` is everything the old mapping created, nothing else is removed or renamed, along
+    // with the entries of other input dirs in the old and new output dirs. Old links are found
+    // there rather than by rerunning `old`, so those of input files since gone are undone too.
+    // `shared_with` are the other input dirs whose output dir is the same as `new`'s.
+    pub fn new(
+        out_dir_path: &str,
+        old: Option<&MappedDir>,
+        new: &MappedDir,
+        ledger: &[LedgerEntry],
+        shared_with: &[&str],
+    ) -> Plan {
+        let mut plan = Plan::diff(out_dir_path, old, new, ledger, shared_with);
+        // invalid rules and filters fall back to linking files under their own names, so they
+        // must never be applied, even when the plan itself found nothing wrong
+        if plan.conflicts.is_empty() {
+            if let Some(error) = new.config_error() {
+                plan.conflicts.push(error);
+            }
+        }
+        plan
+    }
+
+    fn diff(
+        out_dir_path: &str,
+        old: Option<&MappedDir>,
+        new: &MappedDir,
+        ledger: &[LedgerEntry],
+        shared_with: &[&str],
+    ) -> Plan {
+        // even with the same configs, files may have been added to or removed from the input dir
+        let mut plan = Plan {
+            shared_with: shared_with.iter().map(ToString::to_string).collect(),
+            ..Plan::default()
+        };
+
+        let out_dir_name = match new.out_dir_name() {
+            Some(name) if is_inside(&name) => name,
+            Some(name) => {
+                plan.conflicts
+                    .push(format!("`{}` isn't inside the output dir", name));
+                return plan;
+            }
+            None => {
+                plan.conflicts
+                    .push(format!("`{}` has no output dir name", new.in_dir_name()));
+                return plan;
+            }
+        };
+        let new_out_dir_path = join_path(out_dir_path, &out_dir_name);
+        let old_out_dir_path = old.and_then(|old| {
+            old.out_dir_name()
+                .map(|name| join_path(out_dir_path, &name))
+        });
+        let in_dir_path = new.in_dir_path();
+        let owned: HashSet<&str> = ledger
+            .iter()
+            .filter(|entry| entry.in_path == in_dir_path)
+            .map(|entry| entry.out_path.as_str())
+            .collect();
+        let dir_owners = |path: &str| -> Vec<&str> {
+            ledger
+                .iter()
+                .filter(|e| e.kind == EntryKind::Dir && e.out_path == path)
+                .filter(|e| e.in_path != in_dir_path)
+                .map(|e| file_name(&e.in_path))
+                .collect()
+        };
+
+        // parent dirs of the output dir, e.g. `Show` for `Show/Season 01`
+        let mut needed_dirs = sub_dir_paths(out_dir_path, &new_out_dir_path);
+        needed_dirs.pop();
+        for path in needed_dirs.iter() {
+            plan.ensure_dir(path, &owned, &dir_owners(path));
+        }
+
+        // the old output dir is renamed rather than rebuilt, so whatever else is in it survives.
+        // Dirs shared with other input dirs, or that we didn't create, stay where they are.
+        let renamed_from_path = match &old_out_dir_path {
+            Some(old_out_dir_path)
+                if *old_out_dir_path != new_out_dir_path
+                    && owned.contains(old_out_dir_path.as_str())
+                    && !owned.contains(new_out_dir_path.as_str())
+                    && dir_owners(old_out_dir_path).is_empty()
+                    && dir_owners(&new_out_dir_path).is_empty()
+                    && !is_under(&new_out_dir_path, old_out_dir_path)
+                    && !is_under(old_out_dir_path, &new_out_dir_path) =>
+            {
+                plan.check_free(&new_out_dir_path);
+                plan.operations.push(Operation::RenameDir {
+                    from_path: old_out_dir_path.clone(),
+                    to_path: new_out_dir_path.clone(),
+                });
+                Some(old_out_dir_path.clone())
+            }
+            _ => {
+                let new_dir_owners = dir_owners(&new_out_dir_path);
+                if owned.contains(new_out_dir_path.as_str()) {
+                    // e.g. moving back up into a parent dir we created
+                } else if new_dir_owners.is_empty() {
+                    plan.check_free(&new_out_dir_path);
+                    plan.operations.push(Operation::CreateDir {
+                        path: new_out_dir_path.clone(),
+                    });
+                } else {
+                    for owner in new_dir_owners {
+                        if !plan.shared_with.iter().any(|name| name == owner) {
+                            plan.shared_with.push(owner.to_string());
+                        }
+                    }
+                    plan.operations.push(Operation::JoinDir {
+                        path: new_out_dir_path.clone(),
+                    });
+                }
+                None
+            }
+        };
+        needed_dirs.push(new_out_dir_path.clone());
+        // where paths under the old output dir end up once it's renamed, and the other way
+        // around for looking at what's on disk now
+        let moved = |path: &str| match &renamed_from_path {
+            Some(from_path) if is_under(path, from_path) => {
+                new_out_dir_path.clone() + &path[from_path.len()..]
+            }
+            _ => path.to_string(),
+        };
+        let unmoved = |path: &str| match &renamed_from_path {
+            Some(from_path) if is_under(path, &new_out_dir_path) => {
+                from_path.clone() + &path[new_out_dir_path.len()..]
+            }
+            _ => path.to_string(),
+        };
+
+        for to_name in new.duplicate_targets() {
+            let from_names: Vec<_> = new
+                .file_mappings()
+                .iter()
+                .filter_map(|file_mapping| match file_mapping {
+                    FileMapping::MappedTo {
+                        from_name,
+                        to_name: other,
+                    } if other == to_name => Some(format!("`{}`", from_name)),
+                    _ => None,
+                })
+                .collect();
+            plan.conflicts.push(format!(
+                "{} all map to `{}`",
+                from_names.join(", "),
+                to_name
+            ));
+        }
+        for file_mapping in new.file_mappings() {
+            if let FileMapping::MappedTo { to_name, .. } = file_mapping {
+                if !is_inside(&resolve_parents(&join_path(&out_dir_name, to_name))) {
+                    plan.conflicts
+                        .push(format!("`{}` isn't inside the output dir", to_name));
+                }
+            }
+        }
+
+        // the links the ledger says we created, where they'll be after any dir rename, diffed
+        // against the new ones. Links made in another mode are redone.
+        let new_mode = new.link_mode();
+        let new_files = mapped_file_paths(new, &new_out_dir_path);
+        let old_files: Vec<_> = ledger
+            .iter()
+            .filter(|entry| entry.in_path == in_dir_path)
+            .filter_map(|entry| match entry.kind {
+                EntryKind::Link(mode) => {
+                    Some(((entry.source_path.clone(), moved(&entry.out_path)), mode))
+                }
+                EntryKind::Dir => None,
+            })
+            .collect();
+
+        // what's gone from disk once the plan is applied, by its current path
+        let mut removed: HashSet<String> = renamed_from_path.iter().cloned().collect();
+        for (file, old_mode) in old_files.iter() {
+            if *old_mode != new_mode || !new_files.contains(file) {
+                removed.insert(unmoved(&file.1));
+                plan.operations.push(Operation::Unlink {
+                    mode: *old_mode,
+                    in_file_path: file.0.clone(),
+                    out_file_path: file.1.clone(),
+                });
+            }
+        }
+        for file in new_files.iter() {
+            if !old_files.contains(&(file.clone(), new_mode)) {
+                // from the root, routed specials can be in a sibling of the output dir
+                for path in sub_dir_paths(out_dir_path, dir_name(&file.1)) {
+                    if !needed_dirs.contains(&path) {
+                        plan.ensure_dir(&path, &owned, &dir_owners(&path));
+                        needed_dirs.push(path);
+                    }
+                }
+                let on_disk = unmoved(&file.1);
+                if !removed.contains(&on_disk) && fs::symlink_metadata(&on_disk).is_ok() {
+                    plan.conflicts.push(format!("`{}` already exists", file.1));
+                }
+                plan.operations.push(Operation::Link {
+                    mode: new_mode,
+                    in_file_path: file.0.clone(),
+                    out_file_path: file.1.clone(),
+                });
+            }
+        }
+
+        // dirs we no longer need are removed, deepest first, once their last contributor is
+        // gone, unless something other than what we removed is still in them
+        let mut left_dir_paths: Vec<_> = ledger
+            .iter()
+            .filter(|e| e.kind == EntryKind::Dir && e.in_path == in_dir_path)
+            .map(|e| moved(&e.out_path))
+            .filter(|path| !needed_dirs.contains(path))
+            .collect();
+        left_dir_paths.sort_by_key(|path| std::cmp::Reverse(path.matches('/').count()));
+        for path in left_dir_paths {
+            let on_disk = unmoved(&path);
+            let only_has_removed = fs::read_dir(&on_disk)
+                .map(|children| {
+                    children
+                        .filter_map(Result::ok)
+                        .all(|child| removed.contains(child.path().to_string_lossy().as_ref()))
+                })
+                .unwrap_or(false);
+            if dir_owners(&path).is_empty() && only_has_removed {
+                removed.insert(on_disk);
+                plan.operations.push(Operation::RemoveDir { path });
+            } else {
+                plan.operations.push(Operation::LeaveDir { path });
+            }
+        }
+
+        plan
+    }
+
+    // makes sure the dir at `path` exists once applied, joining it if other input dirs own it.
+    // Dirs that are already there and that nobody owns are used as they are.
+    fn ensure_dir(&mut self, path: &str, owned: &HashSet<&str>, owners: &[&str]) {
+        if owned.contains(path) || (owners.is_empty() && Path::new(path).is_dir()) {
+            return;
+        }
+        self.operations.push(if owners.is_empty() {
+            Operation::CreateDir {
+                path: path.to_string(),
+            }
+        } else {
+            Operation::JoinDir {
+                path: path.to_string(),
+            }
+        });
+    }
+
+    fn check_free(&mut self, out_dir_path: &str) {
+        if Path::new(out_dir_path).exists() {
+            self.conflicts
+                .push(format!("`{}` already exists", out_dir_path));
+        }
+    }
+
+    pub fn operations(&self) -> &Vec<Operation> {
+        &self.operations
+    }
+
+    pub fn conflicts(&self) -> &Vec<String> {
+        &self.conflicts
+    }
+
+    pub fn shared_with(&self) -> &Vec<String> {
+        &self.shared_with
+    }
+
+    pub fn can_apply(&self, allow_merge: bool) -> bool {
+        self.conflicts.is_empty() && (self.shared_with.is_empty() || allow_merge)
+    }
+
+    // applies every operation in order, calling `on_applied` after each one, e.g. to record it.
+    // If an operation or `on_applied` fails, the ones already applied are undone in reverse and
+    // the error is returned.
+    pub fn apply(
+        &self,
+        mut on_applied: impl FnMut(&Operation) -> Result<(), String>,
+    ) -> Result<(), String> {
+        for (idx, operation) in self.operations.iter().enumerate() {
+            let (error, num_applied) = match operation.apply() {
+                Err(e) => (format!("{}: {}", operation, e), idx),
+                Ok(()) => match on_applied(operation) {
+                    Ok(()) => continue,
+                    Err(e) => (format!("{}: {}", operation, e), idx + 1),
+                },
+            };
+            return Err(match self.roll_back(num_applied) {
+                Ok(()) => error,
+                Err(rollback_error) => format!("{}, then {}", error, rollback_error),
+            });
+        }
+        Ok(())
+    }
+
+    // undoes the whole plan once it's been applied, e.g. when what it did couldn't be saved
+    pub fn undo(&self) -> Result<(), String> {
+        self.roll_back(self.operations.len())
+    }
+
+    // undoes the first `num_applied` operations, last first
+    fn roll_back(&self, num_applied: usize) -> Result<(), String> {
+        for applied in self.operations[..num_applied].iter().rev() {
+            let inverse = applied.inverse();
+            if let Err(e) = inverse.apply() {
+                return Err(format!("rollback failed at {}: {}", inverse, e));
+            }
+        }
+        Ok(())
+    }
+
+    // e.g. `1 mkdir, 0 rename, 12 link, 0 unlink, 0 rmdir, 0 join, 0 leave`
+    pub fn summary(&self) -> String {
+        let count = |f: fn(&Operation) -> bool| self.operations.iter().filter(|op| f(op)).count();
+        format!(
+            "{} mkdir, {} rename, {} link, {} unlink, {} rmdir, {} join, {} leave",
+            count(|op| matches!(op, Operation::CreateDir { .. })),
+            count(|op| matches!(op, Operation::RenameDir { .. })),
+            count(|op| matches!(op, Operation::Link { .. })),
+            count(|op| matches!(op, Operation::Unlink { .. })),
+            count(|op| matches!(op, Operation::RemoveDir { .. })),
+            count(|op| matches!(op, Operation::JoinDir { .. })),
+            count(|op| matches!(op, Operation::LeaveDir { .. })),
+        )
+    }
+}
+
+impl fmt::Display for Plan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}", self.summary())?;
+        for conflict in self.conflicts.iter() {
+            writeln!(f, "conflict: {}", conflict)?;
+        }
+        if !self.shared_with.is_empty() {
+            writeln!(f, "shared with: {}", self.shared_with.join(", "))?;
+        }
+        for operation in self.operations.iter() {
+            writeln!(f, "{}", operation)?;
+        }
+        Ok(())
+    }
+}
+
+// (input file path, output file path) of each file the mapping links into `out_dir_path`
+fn mapped_file_paths(mapped_dir: &MappedDir, out_dir_path: &str) -> Vec<(String, String)> {
+    mapped_dir
+        .file_mappings()
+        .iter()
+        .filter_map(|file_mapping| match file_mapping {
+            FileMapping::MappedTo { from_name, to_name } => Some((
+                join_path(mapped_dir.in_dir_path(), from_name),
+                resolve_parents(&join_path(out_dir_path, to_name)),
+            )),
+            _ => None,
+        })
+        .collect()
+}
+
+// paths of the dirs from just under `root` down to `path`, e.g. `root/a`, `root/a/b` for
+// `root/a/b`
+fn sub_dir_paths(root: &str, path: &str) -> Vec<String> {
+    let root = root.trim_end_matches('/');
+    if !is_under(path, root) || path.len() == root.len() {
+        return vec![];
+    }
+    let rel_path = path[root.len()..].trim_start_matches('/');
+    rel_path
+        .match_indices('/')
+        .map(|(idx, _)| join_path(root, &rel_path[..idx]))
+        .chain([join_path(root, rel_path)])
+        .collect()
+}
+
+// whether `path` is `dir_path` or somewhere under it
+fn is_under(path: &str, dir_path: &str) -> bool {
+    path.strip_prefix(dir_path)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        app_state::mapping_state::MappingState,
+        ledger::{EntryKind, LedgerEntry},
+        link_mode::LinkMode,
+        test_dir::TestDir,
+    };
+
+    use super::{Operation, Plan};
+
+    const SHIROKUMA: &str = "fixtures/in_dir_1/[Orphan] Shirokuma Cafe (BD 720p)";
+    const LDR: &str =
+        "fixtures/in_dir_1/Love.Death.and.Robots.S03.1080p.NF.WEBRip.DDP5.1.Atmos.x264-SMURF[rartv]";
+
+    // what the ledger holds once `plan` is applied
+    fn ledger_of(in_path: &str, plan: &Plan) -> Vec<LedgerEntry> {
+        plan.operations()
+            .iter()
+            .filter_map(|operation| LedgerEntry::created_by(in_path, operation))
+            .collect()
+    }
+
+    #[test]
+    fn plans_new_mapping() {
+        let mapped_dir = MappingState::Unmapped {
+            in_path: SHIROKUMA.to_string(),
+        }
+        .to_mapped_dir();
+        let plan = Plan::new("/nonexistent", None, &mapped_dir, &[], &[]);
+
+        assert!(plan.conflicts().is_empty());
+        assert_eq!(
+            "1 mkdir, 0 rename, 26 link, 0 unlink, 0 rmdir, 0 join, 0 leave",
+            plan.summary()
+        );
+        assert_eq!(
+            Operation::CreateDir {
+                path: "/nonexistent/Shirokuma Cafe".to_string()
+            },
+            plan.operations()[0]
+        );
+        assert_eq!(
+            "link   /nonexistent/Shirokuma Cafe/Shirokuma Cafe - S01E01.mkv <- \
+            fixtures/in_dir_1/[Orphan] Shirokuma Cafe (BD 720p)/\
+            [Orphan] Shirokuma Cafe - 01v2 (BD 720p) [35A192D6].mkv (relative-symlink)",
+            plan.operations()[1].to_string()
+        );
+    }
+
+    #[test]
+    fn plans_remapping() {
+        let old = MappingState::Unmapped {
+            in_path: SHIROKUMA.to_string(),
+        }
+        .to_mapped_dir();
+        let ledger = ledger_of(SHIROKUMA, &Plan::new("/nonexistent", None, &old, &[], &[]));
+
+        // nothing changed
+        let plan = Plan::new("/nonexistent", Some(&old), &old, &ledger, &[]);
+        assert_eq!(
+            "0 mkdir, 0 rename, 0 link, 0 unlink, 0 rmdir, 0 join, 0 leave",
+            plan.summary()
+        );
+
+        // same output dir, files are relinked in place
+        let mut new = old.clone();
+        new.cycle_link_mode();
+        let plan = Plan::new("/nonexistent", Some(&old), &new, &ledger, &[]);
+        assert_eq!(
+            "0 mkdir, 0 rename, 26 link, 26 unlink, 0 rmdir, 0 join, 0 leave",
+            plan.summary()
+        );
+
+        // only the dir name changed
+        let mut new = old.clone();
+        new.set_config(2, "Shirokuma");
+        let plan = Plan::new("/nonexistent", Some(&old), &new, &ledger, &[]);
+        assert_eq!(
+            vec![Operation::RenameDir {
+                from_path: "/nonexistent/Shirokuma Cafe".to_string(),
+                to_path: "/nonexistent/Shirokuma".to_string(),
+            }],
+            *plan.operations()
+        );
+
+        // nothing we didn't create is renamed or removed
+        let plan = Plan::new("/nonexistent", Some(&old), &new, &[], &[]);
+        assert_eq!(
+            "1 mkdir, 0 rename, 26 link, 0 unlink, 0 rmdir, 0 join, 0 leave",
+            plan.summary()
+        );
+
+        // one episode renamed, the rest of the links are kept
+        let mut new = old.clone();
+        new.add_rule(0);
+        new.set_rule_config(1, 0, "S01E26$");
+        new.set_rule_config(1, 1, "S01E26 - Finale");
+        new.toggle_rule_chain(0);
+        let plan = Plan::new("/nonexistent", Some(&old), &new, &ledger, &[]);
+        assert_eq!(
+            "0 mkdir, 0 rename, 1 link, 1 unlink, 0 rmdir, 0 join, 0 leave",
+            plan.summary()
+        );
+
+        // links of input files that are gone are still undone
+        let mut gone_ledger = ledger.clone();
+        gone_ledger.push(LedgerEntry {
+            out_path: "/nonexistent/Shirokuma Cafe/Gone.mkv".to_string(),
+            in_path: SHIROKUMA.to_string(),
+            source_path: format!("{}/Gone.mkv", SHIROKUMA),
+            kind: EntryKind::Link(LinkMode::Move),
+        });
+        let plan = Plan::new("/nonexistent", Some(&old), &new, &gone_ledger, &[]);
+        assert_eq!(
+            "0 mkdir, 0 rename, 1 link, 2 unlink, 0 rmdir, 0 join, 0 leave",
+            plan.summary()
+        );
+        assert!(plan.operations().contains(&Operation::Unlink {
+            mode: LinkMode::Move,
+            in_file_path: format!("{}/Gone.mkv", SHIROKUMA),
+            out_file_path: "/nonexistent/Shirokuma Cafe/Gone.mkv".to_string(),
+        }));
+
+        // files collide
+        let mut new = old.clone();
+        new.set_rule_config(0, 1, "same name");
+        let plan = Plan::new("/nonexistent", Some(&old), &new, &ledger, &[]);
+        assert_eq!(1, plan.conflicts().len());
+        assert!(plan.conflicts()[0].ends_with(" all map to `same name.mkv`"));
+
+        // invalid rules fall back to the original names, which mustn't be linked
+        let mut new = old.clone();
+        new.set_rule_config(0, 0, "(");
+        let plan = Plan::new("/nonexistent", Some(&old), &new, &ledger, &[]);
+        assert_eq!(1, plan.conflicts().len());
+        assert!(plan.conflicts()[0].starts_with("file rule 1: "));
+        assert!(!plan.can_apply(true));
+
+        // output dir claimed by another input
+        let plan = Plan::new("/nonexistent", None, &old, &[], &["other"]);
+        assert!(plan.conflicts().is_empty());
+        assert!(!plan.can_apply(false));
+        assert!(plan.can_apply(true));
+    }
+
+    #[test]
+    fn plans_merging() {
+        let shirokuma = MappingState::Unmapped {
+            in_path: SHIROKUMA.to_string(),
+        }
+        .to_mapped_dir();
+        let mut ledger = ledger_of(
+            SHIROKUMA,
+            &Plan::new("/nonexistent", None, &shirokuma, &[], &[]),
+        );
+
+        // joins the dir the other input created
+        let mut ldr = MappingState::Unmapped {
+            in_path: LDR.to_string(),
+        }
+        .to_mapped_dir();
+        ldr.set_config(2, "Shirokuma Cafe");
+        let plan = Plan::new("/nonexistent", None, &ldr, &ledger, &[]);
+        assert_eq!(
+            Operation::JoinDir {
+                path: "/nonexistent/Shirokuma Cafe".to_string()
+            },
+            plan.operations()[0]
+        );
+        assert_eq!(
+            "0 mkdir, 0 rename, 9 link, 0 unlink, 0 rmdir, 1 join, 0 leave",
+            plan.summary()
+        );
+        assert_eq!(
+            vec!["[Orphan] Shirokuma Cafe (BD 720p)"],
+            *plan.shared_with()
+        );
+        ledger.extend(ledger_of(LDR, &plan));
+
+        // the shared dir stays behind when one of its contributors moves out
+        let mut new = shirokuma.clone();
+        new.set_config(2, "Shirokuma");
+        let plan = Plan::new("/nonexistent", Some(&shirokuma), &new, &ledger, &[]);
+        assert_eq!(
+            "1 mkdir, 0 rename, 26 link, 26 unlink, 0 rmdir, 0 join, 1 leave",
+            plan.summary()
+        );
+        assert_eq!(
+            Some(&Operation::LeaveDir {
+                path: "/nonexistent/Shirokuma Cafe".to_string()
+            }),
+            plan.operations().last()
+        );
+    }
+
+    #[test]
+    fn plans_nested_dirs() {
+        let mut season = MappingState::Unmapped {
+            in_path: SHIROKUMA.to_string(),
+        }
+        .to_mapped_dir();
+        season.set_config(2, "Shirokuma Cafe/Season 01");
+        let plan = Plan::new("/nonexistent", None, &season, &[], &[]);
+        assert!(plan.conflicts().is_empty());
+        assert_eq!(
+            "2 mkdir, 0 rename, 26 link, 0 unlink, 0 rmdir, 0 join, 0 leave",
+            plan.summary()
+        );
+        assert_eq!(
+            Operation::CreateDir {
+                path: "/nonexistent/Shirokuma Cafe".to_string()
+            },
+            plan.operations()[0]
+        );
+        let ledger = ledger_of(SHIROKUMA, &plan);
+
+        // files moved into a subfolder
+        let mut new = season.clone();
+        new.add_rule(0);
+        new.set_rule_config(1, 0, "(.+)");
+        new.set_rule_config(1, 1, "Extras/$1");
+        new.toggle_rule_chain(0);
+        let plan = Plan::new("/nonexistent", Some(&season), &new, &ledger, &[]);
+        assert_eq!(
+            "1 mkdir, 0 rename, 26 link, 26 unlink, 0 rmdir, 0 join, 0 leave",
+            plan.summary()
+        );
+        assert_eq!(
+            Operation::CreateDir {
+                path: "/nonexistent/Shirokuma Cafe/Season 01/Extras".to_string()
+            },
+            plan.operations()[26]
+        );
+
+        // only the season dir is renamed, its parent is kept
+        let mut new = season.clone();
+        new.set_config(2, "Shirokuma Cafe/Season 1");
+        let plan = Plan::new("/nonexistent", Some(&season), &new, &ledger, &[]);
+        assert_eq!(
+            vec![Operation::RenameDir {
+                from_path: "/nonexistent/Shirokuma Cafe/Season 01".to_string(),
+                to_path: "/nonexistent/Shirokuma Cafe/Season 1".to_string(),
+            }],
+            *plan.operations()
+        );
+
+        // back up into the parent, the season dir is left behind
+        let mut new = season.clone();
+        new.set_config(2, "Shirokuma Cafe");
+        let plan = Plan::new("/nonexistent", Some(&season), &new, &ledger, &[]);
+        assert_eq!(
+            "0 mkdir, 0 rename, 26 link, 26 unlink, 0 rmdir, 0 join, 1 leave",
+            plan.summary()
+        );
+        assert_eq!(
+            Some(&Operation::LeaveDir {
+                path: "/nonexistent/Shirokuma Cafe/Season 01".to_string()
+            }),
+            plan.operations().last()
+        );
+
+        // outputs stay inside the output dir
+        let mut new = season.clone();
+        new.set_config(2, "../Shirokuma Cafe");
+        let plan = Plan::new("/nonexistent", None, &new, &[], &[]);
+        assert_eq!(
+            vec!["`../Shirokuma Cafe` isn't inside the output dir"],
+            *plan.conflicts()
+        );
+        assert!(new.config_error().is_some());
+    }
+
+    #[test]
+    fn plans_new_input_files() {
+        let dir = TestDir::new("plan-added");
+        dir.write("Show - 01.mkv", "");
+        let unmapped = MappingState::Unmapped {
+            in_path: dir.root().to_string_lossy().to_string(),
+        };
+        let old = unmapped.to_mapped_dir();
+        let ledger = ledger_of(
+            old.in_dir_path(),
+            &Plan::new("/nonexistent", None, &old, &[], &[]),
+        );
+
+        // same configs, one more episode
+        dir.write("Show - 02.mkv", "");
+        let new = unmapped.to_mapped_dir();
+        assert!(old.configs_eq(&new));
+        let plan = Plan::new("/nonexistent", Some(&old), &new, &ledger, &[]);
+        assert_eq!(
+            "0 mkdir, 0 rename, 1 link, 0 unlink, 0 rmdir, 0 join, 0 leave",
+            plan.summary()
+        );
+    }
+
+    #[test]
+    fn plans_routed_specials() {
+        let dir = TestDir::new("plan-specials");
+        dir.write("Show - 01.mkv", "");
+        dir.write("Show - SP01.mkv", "");
+        let mut season = MappingState::Unmapped {
+            in_path: dir.root().to_string_lossy().to_string(),
+        }
+        .to_mapped_dir();
+        season.set_config(1, "(.+)");
+        season.set_config(2, "Show/Season 03");
+
+        // specials go in a sibling of the season dir
+        let plan = Plan::new("/nonexistent", None, &season, &[], &[]);
+        assert!(plan.conflicts().is_empty());
+        assert_eq!(
+            "3 mkdir, 0 rename, 2 link, 0 unlink, 0 rmdir, 0 join, 0 leave",
+            plan.summary()
+        );
+        assert!(plan.operations().contains(&Operation::CreateDir {
+            path: "/nonexistent/Show/Season 00".to_string()
+        }));
+        assert!(plan.operations().contains(&Operation::Link {
+            mode: LinkMode::RelativeSymlink,
+            in_file_path: dir.path("Show - SP01.mkv"),
+            out_file_path: "/nonexistent/Show/Season 00/Show - S00E01 - SP 01.mkv".to_string(),
+        }));
+    }
+
+    #[test]
+    fn rolls_back_failed_apply() {
+        let dir = TestDir::new("plan");
+        let path = |name: &str| dir.path(name);
+        dir.create_dir("old");
+        dir.write("in/a.mkv", "a");
+        dir.write("in/b.mkv", "b");
+
+        let link = |in_name: &str, out_name: &str| Operation::Link {
+            mode: LinkMode::Hardlink,
+            in_file_path: path(in_name),
+            out_file_path: path(out_name),
+        };
+        let plan = Plan {
+            operations: vec![
+                Operation::RenameDir {
+                    from_path: path("old"),
+                    to_path: path("new"),
+                },
+                Operation::CreateDir { path: path("new2") },
+                link("in/a.mkv", "new/a.mkv"),
+                link("in/b.mkv", "new/a.mkv"),
+            ],
+            conflicts: vec![],
+            shared_with: vec![],
+        };
+
+        let mut num_applied = 0;
+        let error = plan
+            .apply(|_| {
+                num_applied += 1;
+                Ok(())
+            })
+            .unwrap_err();
+        assert!(error.starts_with(&format!("link   {} <- ", path("new/a.mkv"))));
+        assert_eq!(3, num_applied);
+        let root = dir.root();
+        assert!(root.join("old").is_dir());
+        assert!(!root.join("new").exists());
+        assert!(!root.join("new2").exists());
+        assert!(!root.join("old/a.mkv").exists());
+
+        // failing to record an operation undoes it too
+        let error = plan
+            .apply(|operation| match operation {
+                Operation::CreateDir { .. } => Err("database: disk full".to_string()),
+                _ => Ok(()),
+            })
+            .unwrap_err();
+        assert!(error.ends_with(": database: disk full"));
+        assert!(root.join("old").is_dir());
+        assert!(!root.join("new").exists());
+        assert!(!root.join("new2").exists());
+    }
+}
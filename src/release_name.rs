@@ -0,0 +1,419 @@
+use std::sync::OnceLock;
+
+use regex::Regex;
+
+// Fields pulled out of a scene (`Show.S03E01.Title.1080p.NF.WEB-DL...-GROUP`) or fansub
+// (`[Group] Show - 01v2 (BD 720p) [CRC]`) style release name. `name` should not include the
+// file extension.
+#[derive(Debug, Default, PartialEq)]
+pub struct ReleaseName {
+    pub title: String,
+    pub season: Option<u32>,
+    pub episode: Option<u32>,
+    pub version: Option<u32>,
+    pub episode_title: Option<String>,
+    pub resolution: Option<String>,
+    pub source: Option<String>,
+    pub group: Option<String>,
+    pub crc: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ReleaseStyle {
+    Scene,
+    Fansub,
+}
+
+// Dir and file matcher / replacer configs that reproduce a Plex friendly layout
+#[derive(Debug, PartialEq)]
+pub struct Suggestion {
+    pub dir_matcher: String,
+    pub dir_replacer: String,
+    pub file_matcher: String,
+    pub file_replacer: String,
+}
+
+const SOURCES: &[&str] = &[
+    "AMZN", "ATVP", "BD", "BDRip", "BluRay", "DSNP", "DVD", "DVDRip", "HDTV", "HMAX", "HULU", "NF",
+    "TV", "WEB", "WEB-DL", "WEBRip",
+];
+
+pub fn parse(name: &str) -> Option<(ReleaseStyle, ReleaseName)> {
+    if let Some(release) = parse_fansub(name) {
+        return Some((ReleaseStyle::Fansub, release));
+    }
+    parse_scene(name).map(|release| (ReleaseStyle::Scene, release))
+}
+
+fn parse_scene(name: &str) -> Option<ReleaseName> {
+    // compiled once, parsing runs for every file on every keystroke
+    static RE: OnceLock<Regex> = OnceLock::new();
+    static TRACKER_RE: OnceLock<Regex> = OnceLock::new();
+    let re = RE.get_or_init(|| {
+        Regex::new(
+            r"(?i)^(?P<title>.+?)[. _-]S(?P<season>\d{1,2})(?:E(?P<episode>\d{1,3}))?(?:[. _-](?P<rest>.*))?$",
+        )
+        .unwrap()
+    });
+    let captures = re.captures(name)?;
+
+    let mut release = ReleaseName {
+        title: captures["title"]
+            .replace(['.', '_'], " ")
+            .trim()
+            .to_string(),
+        season: captures["season"].parse().ok(),
+        episode: captures
+            .name("episode")
+            .and_then(|e| e.as_str().parse().ok()),
+        ..Default::default()
+    };
+
+    let mut rest = captures.name("rest").map_or("", |rest| rest.as_str());
+
+    // trailing `[tracker]` tag, then `-GROUP`
+    let tracker_re = TRACKER_RE.get_or_init(|| Regex::new(r"\[[^\]]*\]$").unwrap());
+    if let Some(tracker) = tracker_re.find(rest) {
+        rest = &rest[..tracker.start()];
+    }
+    if let Some(dash) = rest.rfind('-') {
+        let group = &rest[dash + 1..];
+        if !group.is_empty() && group.chars().all(|c| c.is_ascii_alphanumeric()) {
+            release.group = Some(group.to_string());
+            rest = &rest[..dash];
+        }
+    }
+
+    let mut title_words = vec![];
+    let mut in_title = true;
+    for token in rest.split('.').filter(|t| !t.is_empty()) {
+        if is_resolution(token) {
+            release.resolution.get_or_insert_with(|| token.to_string());
+        } else if let Some(source) = as_source(token) {
+            release.source.get_or_insert_with(|| source.to_string());
+        } else if in_title {
+            title_words.push(token);
+            continue;
+        }
+        in_title = false;
+    }
+    if !title_words.is_empty() {
+        release.episode_title = Some(title_words.join(" "));
+    }
+
+    Some(release)
+}
+
+fn parse_fansub(name: &str) -> Option<ReleaseName> {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    static TAG_RE: OnceLock<Regex> = OnceLock::new();
+    let re = RE.get_or_init(|| {
+        Regex::new(
+            r"^\[(?P<group>[^\]]+)\]\s*(?P<title>[^\[(]+?)(?:\s+-\s+(?P<episode>\d{1,4})(?:v(?P<version>\d+))?)?\s*(?P<tags>(?:[\[(][^\])]*[\])]\s*)*)$",
+        )
+        .unwrap()
+    });
+    let captures = re.captures(name)?;
+
+    let mut release = ReleaseName {
+        title: captures["title"].trim().to_string(),
+        episode: captures
+            .name("episode")
+            .and_then(|e| e.as_str().parse().ok()),
+        version: captures
+            .name("version")
+            .and_then(|v| v.as_str().parse().ok()),
+        group: Some(captures["group"].to_string()),
+        ..Default::default()
+    };
+
+    let tag_re = TAG_RE.get_or_init(|| Regex::new(r"[\[(]([^\])]*)[\])]").unwrap());
+    for tag in tag_re.captures_iter(&captures["tags"]) {
+        let tag = &tag[1];
+        if tag.len() == 8 && tag.chars().all(|c| c.is_ascii_hexdigit()) {
+            release.crc = Some(tag.to_uppercase());
+            continue;
+        }
+        for word in tag.split_whitespace() {
+            if is_resolution(word) {
+                release.resolution.get_or_insert_with(|| word.to_string());
+            } else if let Some(source) = as_source(word) {
+                release.source.get_or_insert_with(|| source.to_string());
+            }
+        }
+    }
+
+    Some(release)
+}
+
+fn is_resolution(token: &str) -> bool {
+    let digits = token.strip_suffix('p').or_else(|| token.strip_suffix('P'));
+    matches!(digits, Some(d) if (3..=4).contains(&d.len()) && d.chars().all(|c| c.is_ascii_digit()))
+}
+
+fn as_source(token: &str) -> Option<&'static str> {
+    SOURCES
+        .iter()
+        .find(|source| source.eq_ignore_ascii_case(token))
+        .copied()
+}
+
+// `name` without the version suffix of its episode number, and the version, e.g.
+// `Show - 01v2 (BD)` -> (`Show - 01 (BD)`, 2). Unversioned names are version 1. Only the first
+// episode number counts, `S01E02`, ` - 02` or a name starting with `02`, so a title like
+// `Show - S01E02 - 4v4` isn't versioned.
+pub fn split_version(name: &str) -> (String, u32) {
+    static EPISODE_RE: OnceLock<Regex> = OnceLock::new();
+    static VERSION_RE: OnceLock<Regex> = OnceLock::new();
+    let episode_re = EPISODE_RE
+        .get_or_init(|| Regex::new(r"(?i)\bS\d{1,2}E\d{1,4}|\s-\s\d{1,4}|(?:^|/)\d{1,4}").unwrap());
+    let version_re = VERSION_RE.get_or_init(|| Regex::new(r"^[vV](\d{1,2})\b").unwrap());
+
+    let version = episode_re.find(name).and_then(|episode| {
+        let captures = version_re.captures(&name[episode.end()..])?;
+        Some((episode.end(), captures[0].len(), captures[1].parse().ok()?))
+    });
+    match version {
+        Some((start, len, version)) => (
+            format!("{}{}", &name[..start], &name[start + len..]),
+            version,
+        ),
+        None => (name.to_string(), 1),
+    }
+}
+
+// dir matcher / replacer giving `Title (Year)` for a `Title.Year...` or `Title (Year)` movie
+// release
+pub fn suggest_movie_dir(dir_name: &str) -> Option<(String, String)> {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    let matcher = r"^(?P<title>.+?)[. _(\[]+(?P<year>(?:19|20)\d{2})\b";
+    let re = RE.get_or_init(|| Regex::new(matcher).unwrap());
+    re.is_match(dir_name).then(|| {
+        (
+            matcher.to_string(),
+            "${title|replace:.: |replace:_: } (${year})".to_string(),
+        )
+    })
+}
+
+// Builds matchers / replacers for a dir and its files, based on the dir name and the first
+// file name that parses as an episode. None if nothing recognizable was found.
+pub fn suggest(dir_name: &str, file_basenames: &[&str]) -> Option<Suggestion> {
+    let (dir_style, dir_release) = parse(dir_name)?;
+    let (file_style, file_release) = file_basenames
+        .iter()
+        .filter_map(|name| parse(name))
+        .find(|(_, release)| release.episode.is_some())?;
+
+    let dir_matcher = match dir_style {
+        ReleaseStyle::Scene => r"^(?P<title>.+?)[. _-][Ss]\d{1,2}\b",
+        ReleaseStyle::Fansub => r"^\[[^\]]*\]\s*(?P<title>[^\[(]+?)\s*(?:[\[(].*)?$",
+    };
+    let dir_replacer = match dir_style {
+        ReleaseStyle::Scene => "${title|replace:.: |replace:_: }",
+        ReleaseStyle::Fansub => "${title}",
+    };
+
+    let show = dir_release.title.replace('$', "$$");
+    let (file_matcher, file_replacer) = match file_style {
+        ReleaseStyle::Scene => {
+            let episode = r"[Ss](?P<season>\d+)[Ee](?P<ep>\d+)";
+            let prefix = format!("{} - S${{season|pad:2}}E${{ep|pad:2}}", show);
+            match (&file_release.episode_title, &file_release.resolution) {
+                (Some(_), Some(_)) => (
+                    format!(r"{}\.(?P<name>.+?)\.\d{{3,4}}[pP]\b", episode),
+                    format!("{} - ${{name|replace:.: }}", prefix),
+                ),
+                _ => (episode.to_string(), prefix),
+            }
+        }
+        ReleaseStyle::Fansub => {
+            let season = dir_release.season.or(file_release.season).unwrap_or(1);
+            (
+                r"\s-\s(?P<ep>\d+)(?:v\d+)?\b".to_string(),
+                format!("{} - S{:02}E${{ep|pad:2}}", show, season),
+            )
+        }
+    };
+
+    Some(Suggestion {
+        dir_matcher: dir_matcher.to_string(),
+        dir_replacer: dir_replacer.to_string(),
+        file_matcher,
+        file_replacer,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use crate::renamer::Renamer;
+
+    use super::{parse, split_version, suggest, suggest_movie_dir, ReleaseName, ReleaseStyle};
+
+    const FIXTURES_DIR: &str = "fixtures/in_dir_1";
+
+    fn fixture_file_basenames(dir_name: &str) -> Vec<String> {
+        let mut names: Vec<_> = std::fs::read_dir(format!("{}/{}", FIXTURES_DIR, dir_name))
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+            .filter(|name| name.ends_with(".mkv"))
+            .map(|name| name.trim_end_matches(".mkv").to_string())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn parses_scene_names() {
+        assert_eq!(
+            Some((
+                ReleaseStyle::Scene,
+                ReleaseName {
+                    title: "Love Death and Robots".to_string(),
+                    season: Some(3),
+                    episode: Some(1),
+                    episode_title: Some("Three Robots Exit Strategies".to_string()),
+                    resolution: Some("1080p".to_string()),
+                    source: Some("NF".to_string()),
+                    group: Some("SMURF".to_string()),
+                    ..Default::default()
+                }
+            )),
+            parse("Love.Death.and.Robots.S03E01.Three.Robots.Exit.Strategies.1080p.NF.WEB-DL.DDP5.1.Atmos.x264-SMURF")
+        );
+
+        assert_eq!(
+            Some((
+                ReleaseStyle::Scene,
+                ReleaseName {
+                    title: "Love Death and Robots".to_string(),
+                    season: Some(3),
+                    resolution: Some("1080p".to_string()),
+                    source: Some("NF".to_string()),
+                    group: Some("SMURF".to_string()),
+                    ..Default::default()
+                }
+            )),
+            parse("Love.Death.and.Robots.S03.1080p.NF.WEBRip.DDP5.1.Atmos.x264-SMURF[rartv]")
+        );
+    }
+
+    #[test]
+    fn parses_fansub_names() {
+        assert_eq!(
+            Some((
+                ReleaseStyle::Fansub,
+                ReleaseName {
+                    title: "Shirokuma Cafe".to_string(),
+                    episode: Some(11),
+                    version: Some(2),
+                    resolution: Some("720p".to_string()),
+                    source: Some("BD".to_string()),
+                    group: Some("Orphan".to_string()),
+                    crc: Some("0211A952".to_string()),
+                    ..Default::default()
+                }
+            )),
+            parse("[Orphan] Shirokuma Cafe - 11v2 (BD 720p) [0211A952]")
+        );
+
+        let (_, dir) = parse("[Orphan] Shirokuma Cafe (BD 720p)").unwrap();
+        assert_eq!("Shirokuma Cafe", dir.title);
+        assert_eq!(None, dir.episode);
+        assert_eq!(None, dir.crc);
+    }
+
+    #[test]
+    fn parses_all_fixtures() {
+        for dir_name in [
+            "Love.Death.and.Robots.S03.1080p.NF.WEBRip.DDP5.1.Atmos.x264-SMURF[rartv]",
+            "[Orphan] Shirokuma Cafe (BD 720p)",
+        ] {
+            for (idx, name) in fixture_file_basenames(dir_name).iter().enumerate() {
+                let (_, release) = parse(name).unwrap();
+                assert_eq!(Some(idx as u32 + 1), release.episode, "{}", name);
+            }
+        }
+    }
+
+    fn apply(dir_name: &str, file_basename: &str) -> (String, String) {
+        let files = fixture_file_basenames(dir_name);
+        let files: Vec<_> = files.iter().map(String::as_str).collect();
+        let suggestion = suggest(dir_name, &files).unwrap();
+        let dir_renamer = Renamer::new(&suggestion.dir_matcher, &suggestion.dir_replacer).unwrap();
+        let file_renamer =
+            Renamer::new(&suggestion.file_matcher, &suggestion.file_replacer).unwrap();
+        (
+            dir_renamer.process(dir_name).unwrap(),
+            file_renamer.process(file_basename).unwrap(),
+        )
+    }
+
+    #[test]
+    fn suggests_configs_for_fixtures() {
+        assert_eq!(
+            (
+                "Love Death and Robots".to_string(),
+                "Love Death and Robots - S03E07 - Masons Rats".to_string()
+            ),
+            apply(
+                "Love.Death.and.Robots.S03.1080p.NF.WEBRip.DDP5.1.Atmos.x264-SMURF[rartv]",
+                "Love.Death.and.Robots.S03E07.Masons.Rats.1080p.NF.WEB-DL.DDP5.1.Atmos.x264-SMURF"
+            )
+        );
+
+        assert_eq!(
+            (
+                "Shirokuma Cafe".to_string(),
+                "Shirokuma Cafe - S01E26".to_string()
+            ),
+            apply(
+                "[Orphan] Shirokuma Cafe (BD 720p)",
+                "[Orphan] Shirokuma Cafe - 26v2 (BD 720p) [42E205D3]"
+            )
+        );
+
+        assert_eq!(None, suggest("some dir", &["some file"]));
+    }
+
+    #[test]
+    fn splits_versions() {
+        assert_eq!(
+            ("[Orphan] Show - 01 (BD 720p).mkv".to_string(), 2),
+            split_version("[Orphan] Show - 01v2 (BD 720p).mkv")
+        );
+        assert_eq!(
+            ("Show - S01E03.mkv".to_string(), 3),
+            split_version("Show - S01E03v3.mkv")
+        );
+        assert_eq!(
+            ("Show - 01.mkv".to_string(), 1),
+            split_version("Show - 01.mkv")
+        );
+        assert_eq!(("Dev.mkv".to_string(), 1), split_version("Dev.mkv"));
+        assert_eq!(
+            ("Season 1/01 [BD].mkv".to_string(), 2),
+            split_version("Season 1/01v2 [BD].mkv")
+        );
+
+        // only right after the episode number
+        assert_eq!(
+            ("Show - S01E02 - 4v4.mkv".to_string(), 1),
+            split_version("Show - S01E02 - 4v4.mkv")
+        );
+        assert_eq!(
+            ("Team 4v4 - 03.mkv".to_string(), 1),
+            split_version("Team 4v4 - 03.mkv")
+        );
+    }
+
+    #[test]
+    fn suggests_movie_dirs() {
+        for dir_name in ["Heat.1995.1080p.BluRay.x264-GROUP", "Heat (1995) [1080p]"] {
+            let (matcher, replacer) = suggest_movie_dir(dir_name).unwrap();
+            let renamer = Renamer::new(&matcher, &replacer).unwrap();
+            assert_eq!(Some("Heat (1995)".to_string()), renamer.process(dir_name));
+        }
+        assert_eq!(None, suggest_movie_dir("Heat"));
+    }
+}
@@ -0,0 +1,305 @@
+use regex::escape;
+
+use crate::renamer::Renamer;
+
+// Infers a (matcher, replacer) pair that renames `in_name` to `out_name`.
+//
+// Alphanumeric runs of `out_name` are matched against those of `in_name` (right to left, so
+// the last of two equal numbers wins, e.g. the episode in `S01E01`). Matched runs that are the
+// same in every one of `siblings` are kept literal, the rest become captures: digits as `\d+`,
+// anything else as `.+?` with separators translated via `replace` filters.
+pub fn infer_rule(
+    in_name: &str,
+    out_name: &str,
+    siblings: &[&str],
+) -> Result<(String, String), String> {
+    let in_tokens = tokenize(in_name);
+    let out_tokens = tokenize(out_name);
+    let spans = match_spans(in_name, &in_tokens, out_name, &out_tokens);
+
+    let is_constant = |text: &str| siblings.iter().all(|sibling| sibling.contains(text));
+
+    let mut matcher_parts: Vec<(usize, usize, String)> = vec![];
+    let mut replacer = String::new();
+    let mut num_groups = 0;
+    let mut out_idx = 0;
+    let mut out_pos = 0;
+
+    while out_idx < out_tokens.len() {
+        let span = spans.iter().find(|span| span.out_first == out_idx);
+        let span = match span {
+            Some(span) => span,
+            None => {
+                let token = &out_tokens[out_idx];
+                replacer.push_str(&escape_literal(&out_name[out_pos..token.end]));
+                out_pos = token.end;
+                out_idx += 1;
+                continue;
+            }
+        };
+
+        let first_out = &out_tokens[span.out_first];
+        let last_out = &out_tokens[span.out_last];
+        replacer.push_str(&escape_literal(&out_name[out_pos..first_out.start]));
+
+        let in_start = in_tokens[span.in_first].start;
+        let in_end = in_tokens[span.in_last].end;
+        let in_text = &in_name[in_start..in_end];
+        let span_len = span.in_last - span.in_first;
+
+        if is_constant(in_text) {
+            replacer.push_str(&escape_literal(&out_name[first_out.start..last_out.end]));
+        } else if (0..=span_len).any(|i| in_tokens[span.in_first + i].is_digits) {
+            // numbers become captures, everything else in the span stays literal
+            let mut pattern = String::new();
+            for i in 0..=span_len {
+                let in_token = &in_tokens[span.in_first + i];
+                let out_token = &out_tokens[span.out_first + i];
+                if i > 0 {
+                    let prev_in = &in_tokens[span.in_first + i - 1];
+                    let prev_out = &out_tokens[span.out_first + i - 1];
+                    pattern.push_str(&escape(&in_name[prev_in.end..in_token.start]));
+                    replacer.push_str(&escape_literal(&out_name[prev_out.end..out_token.start]));
+                }
+
+                let out_text = &out_name[out_token.start..out_token.end];
+                if in_token.is_digits {
+                    num_groups += 1;
+                    pattern.push_str(r"(\d+)");
+                    if out_text.len() > 1 && out_text.starts_with('0') {
+                        replacer.push_str(&format!("${{{}|pad:{}}}", num_groups, out_text.len()));
+                    } else {
+                        replacer.push_str(&format!("${{{}}}", num_groups));
+                    }
+                } else {
+                    pattern.push_str(&escape(&in_name[in_token.start..in_token.end]));
+                    replacer.push_str(&escape_literal(out_text));
+                }
+            }
+            matcher_parts.push((in_start, in_end, pattern));
+        } else {
+            num_groups += 1;
+            matcher_parts.push((in_start, in_end, "(.+?)".to_string()));
+
+            let mut filters = String::new();
+            if let Some((in_sep, out_sep)) = span.separators {
+                if in_sep != out_sep {
+                    filters = format!("|replace:{}:{}", in_sep, out_sep);
+                }
+            }
+            replacer.push_str(&format!("${{{}{}}}", num_groups, filters));
+        }
+
+        out_pos = last_out.end;
+        out_idx = span.out_last + 1;
+    }
+    replacer.push_str(&escape_literal(&out_name[out_pos..]));
+
+    // everything around the captures: literal if it never changes, otherwise skipped over
+    matcher_parts.sort_by_key(|(start, _, _)| *start);
+    let mut matcher = "^".to_string();
+    let mut in_pos = 0;
+    for (start, end, pattern) in matcher_parts.iter() {
+        matcher.push_str(&gap_pattern(&in_name[in_pos..*start], &is_constant));
+        matcher.push_str(pattern);
+        in_pos = *end;
+    }
+    matcher.push_str(&gap_pattern(&in_name[in_pos..], &is_constant));
+    matcher.push('$');
+
+    let renamer = Renamer::new(&matcher, &replacer)?;
+    if renamer.process(in_name).as_deref() != Some(out_name) {
+        return Err("can't reproduce the example".to_string());
+    }
+    Ok((matcher, replacer))
+}
+
+struct Token {
+    start: usize,
+    end: usize,
+    is_digits: bool,
+}
+
+// a run of matched tokens, along with the (input, output) separator used between them
+struct Span<'a> {
+    in_first: usize,
+    in_last: usize,
+    out_first: usize,
+    out_last: usize,
+    separators: Option<(&'a str, &'a str)>,
+}
+
+// splits `s` into runs of digits and runs of other alphanumerics
+fn tokenize(s: &str) -> Vec<Token> {
+    let mut tokens: Vec<Token> = vec![];
+    for (idx, c) in s.char_indices() {
+        if !c.is_alphanumeric() {
+            continue;
+        }
+        let is_digits = c.is_ascii_digit();
+        match tokens.last_mut() {
+            Some(last) if last.end == idx && last.is_digits == is_digits => {
+                last.end = idx + c.len_utf8()
+            }
+            _ => tokens.push(Token {
+                start: idx,
+                end: idx + c.len_utf8(),
+                is_digits,
+            }),
+        }
+    }
+    tokens
+}
+
+fn tokens_eq(a: &str, b: &str) -> bool {
+    if a == b {
+        return true;
+    }
+    match (a.parse::<u64>(), b.parse::<u64>()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+fn match_spans<'a>(
+    in_name: &'a str,
+    in_tokens: &[Token],
+    out_name: &'a str,
+    out_tokens: &[Token],
+) -> Vec<Span<'a>> {
+    let text = |s: &'a str, t: &Token| &s[t.start..t.end];
+    let mut in_used = vec![false; in_tokens.len()];
+    let mut spans = vec![];
+
+    let mut out_idx = out_tokens.len();
+    while out_idx > 0 {
+        out_idx -= 1;
+        let out_text = text(out_name, &out_tokens[out_idx]);
+
+        let mut best: Option<Span> = None;
+        for in_idx in (0..in_tokens.len()).rev() {
+            if in_used[in_idx] || !tokens_eq(text(in_name, &in_tokens[in_idx]), out_text) {
+                continue;
+            }
+
+            // extend leftwards while tokens are equal and separators translate consistently
+            let mut span = Span {
+                in_first: in_idx,
+                in_last: in_idx,
+                out_first: out_idx,
+                out_last: out_idx,
+                separators: None,
+            };
+            while span.in_first > 0 && span.out_first > 0 && !in_used[span.in_first - 1] {
+                let (prev_in, cur_in) = (&in_tokens[span.in_first - 1], &in_tokens[span.in_first]);
+                let (prev_out, cur_out) =
+                    (&out_tokens[span.out_first - 1], &out_tokens[span.out_first]);
+                if !tokens_eq(text(in_name, prev_in), text(out_name, prev_out)) {
+                    break;
+                }
+                let in_sep = &in_name[prev_in.end..cur_in.start];
+                let out_sep = &out_name[prev_out.end..cur_out.start];
+                match (in_sep.is_empty(), out_sep.is_empty(), span.separators) {
+                    (true, true, _) => {}
+                    (false, false, None) => span.separators = Some((in_sep, out_sep)),
+                    (false, false, Some(seps)) if seps == (in_sep, out_sep) => {}
+                    _ => break,
+                }
+                span.in_first -= 1;
+                span.out_first -= 1;
+            }
+
+            let is_longer = match &best {
+                Some(best) => span.out_last - span.out_first > best.out_last - best.out_first,
+                None => true,
+            };
+            if is_longer {
+                best = Some(span);
+            }
+        }
+
+        if let Some(span) = best {
+            in_used[span.in_first..=span.in_last].fill(true);
+            out_idx = span.out_first;
+            spans.push(span);
+        }
+    }
+    spans
+}
+
+fn gap_pattern(gap: &str, is_constant: &dyn Fn(&str) -> bool) -> String {
+    if gap.is_empty() {
+        String::new()
+    } else if is_constant(gap) {
+        escape(gap)
+    } else {
+        ".*?".to_string()
+    }
+}
+
+fn escape_literal(s: &str) -> String {
+    s.replace('$', "$$")
+}
+
+#[cfg(test)]
+mod test {
+    use crate::renamer::Renamer;
+
+    use super::infer_rule;
+
+    fn apply(in_name: &str, out_name: &str, siblings: &[&str]) -> Vec<Option<String>> {
+        let (matcher, replacer) = infer_rule(in_name, out_name, siblings).unwrap();
+        let renamer = Renamer::new(&matcher, &replacer).unwrap();
+        siblings.iter().map(|s| renamer.process(s)).collect()
+    }
+
+    #[test]
+    fn infers_scene_rule() {
+        let siblings = [
+            "Love.Death.and.Robots.S03E01.Three.Robots.Exit.Strategies.1080p.NF.WEB-DL.DDP5.1.Atmos.x264-SMURF",
+            "Love.Death.and.Robots.S03E02.Bad.Travelling.1080p.NF.WEB-DL.DDP5.1.Atmos.x264-SMURF",
+            "Love.Death.and.Robots.S03E09.Jibaro.1080p.NF.WEB-DL.DDP5.1.Atmos.x264-SMURF",
+        ];
+        assert_eq!(
+            vec![
+                Some("Love Death and Robots - S03E01 - Three Robots Exit Strategies".to_string()),
+                Some("Love Death and Robots - S03E02 - Bad Travelling".to_string()),
+                Some("Love Death and Robots - S03E09 - Jibaro".to_string()),
+            ],
+            apply(
+                siblings[0],
+                "Love Death and Robots - S03E01 - Three Robots Exit Strategies",
+                &siblings
+            )
+        );
+    }
+
+    #[test]
+    fn infers_fansub_rule() {
+        let siblings = [
+            "[Orphan] Shirokuma Cafe - 01v2 (BD 720p) [35A192D6]",
+            "[Orphan] Shirokuma Cafe - 14 (BD 720p) [4C5B8395]",
+            "[Orphan] Shirokuma Cafe - 26v2 (BD 720p) [42E205D3]",
+        ];
+        assert_eq!(
+            vec![
+                Some("Shirokuma Cafe - S01E01".to_string()),
+                Some("Shirokuma Cafe - S01E14".to_string()),
+                Some("Shirokuma Cafe - S01E26".to_string()),
+            ],
+            apply(siblings[0], "Shirokuma Cafe - S01E01", &siblings)
+        );
+    }
+
+    #[test]
+    fn keeps_unmatched_output_literal() {
+        let siblings = ["show 5 $x", "show 6 $x"];
+        assert_eq!(
+            vec![
+                Some("Episode 005 ($)".to_string()),
+                Some("Episode 006 ($)".to_string()),
+            ],
+            apply(siblings[0], "Episode 005 ($)", &siblings)
+        );
+    }
+}
@@ -1,69 +1,191 @@
 use regex::Regex;
 
+use crate::template::{Template, Variables};
+
 #[derive(Clone)]
 pub struct Renamer {
     finder: Regex,
-    replacer: String,
+    template: Template,
+    variables: Variables,
 }
 
 impl Renamer {
-    pub fn new(finder: &str, replacer: &str) -> Option<Renamer> {
+    pub fn new(finder: &str, replacer: &str) -> Result<Renamer, String> {
         let finder = match Regex::new(finder) {
             Ok(r) => r,
-            Err(_) => return None,
+            Err(e) => return Err(regex_error_message(&e)),
         };
 
-        Some(Renamer {
+        let template = Template::parse(&finder, replacer)?;
+
+        Ok(Renamer {
             finder,
-            replacer: replacer.to_string(),
+            template,
+            variables: Variables::default(),
         })
     }
 
-    pub fn process(&self, input: &str) -> String {
-        let captures = match self.finder.captures(input) {
-            Some(cap) => cap,
-            None => return self.replacer.to_string(),
+    pub fn with_variables(self, variables: Variables) -> Renamer {
+        Renamer { variables, ..self }
+    }
+
+    // None if the finder doesn't match `input`, or a sum in the replacer can't be computed
+    pub fn process(&self, input: &str) -> Option<String> {
+        self.finder
+            .captures(input)
+            .and_then(|captures| self.template.render(&captures, &self.variables))
+    }
+}
+
+// One step of a file rename pipeline. `filter`, if set, must match the input for the rule to
+// apply. A rule that `chain`s feeds its output into the next rule instead of finishing.
+#[derive(Clone)]
+pub struct RenameRule {
+    renamer: Renamer,
+    filter: Option<Regex>,
+    chain: bool,
+}
+
+impl RenameRule {
+    pub fn new(
+        finder: &str,
+        replacer: &str,
+        filter: &str,
+        chain: bool,
+    ) -> Result<RenameRule, String> {
+        let filter = if filter.is_empty() {
+            None
+        } else {
+            match Regex::new(filter) {
+                Ok(r) => Some(r),
+                Err(e) => return Err(format!("filter: {}", regex_error_message(&e))),
+            }
         };
 
-        let mut replaced = self.replacer.to_string();
+        Ok(RenameRule {
+            renamer: Renamer::new(finder, replacer)?,
+            filter,
+            chain,
+        })
+    }
 
-        let mut idx = 1;
-        loop {
-            let pat = format!("${}", idx);
-            let cap = match captures.get(idx) {
-                Some(cap) => cap,
-                None => return replaced,
-            };
+    pub fn with_variables(self, variables: Variables) -> RenameRule {
+        RenameRule {
+            renamer: self.renamer.with_variables(variables),
+            ..self
+        }
+    }
 
-            if replaced.contains(pat.as_str()) {
-                replaced = replaced.replace(pat.as_str(), cap.as_str());
-            } else {
-                return replaced;
+    fn process(&self, input: &str) -> Option<String> {
+        if let Some(filter) = &self.filter {
+            if !filter.is_match(input) {
+                return None;
             }
+        }
+        self.renamer.process(input)
+    }
+}
 
-            idx += 1;
+// Runs `input` through the first rule that matches it, then through every rule it chains into.
+// A chained rule that doesn't match passes its input through unchanged. None if no rule matched.
+pub fn process_rules(rules: &[RenameRule], input: &str) -> Option<String> {
+    let mut rules = rules.iter();
+    let (mut renamed, mut chain) = rules
+        .by_ref()
+        .find_map(|rule| rule.process(input).map(|renamed| (renamed, rule.chain)))?;
+
+    while chain {
+        let rule = match rules.next() {
+            Some(rule) => rule,
+            None => break,
+        };
+        if let Some(chained) = rule.process(&renamed) {
+            renamed = chained;
         }
+        chain = rule.chain;
+    }
+    Some(renamed)
+}
+
+// regex syntax errors span several lines (pattern, caret, message), only keep the message
+fn regex_error_message(e: &regex::Error) -> String {
+    let message = e.to_string();
+    match message.lines().last() {
+        Some(line) => line.trim_start_matches("error: ").to_string(),
+        None => message,
     }
 }
 
 #[cfg(test)]
 mod test {
-    use crate::renamer::Renamer;
+    use crate::{
+        renamer::{process_rules, RenameRule, Renamer},
+        template::Variables,
+    };
 
     #[test]
     fn works() {
         let r = Renamer::new("(.+)", "$1").unwrap();
-        assert_eq!("foo", r.process("foo"));
-        assert_eq!("asd23$1", r.process("asd23$1"));
+        assert_eq!(Some("foo".to_string()), r.process("foo"));
+        assert_eq!(Some("asd23$1".to_string()), r.process("asd23$1"));
 
         let r = Renamer::new("(.+)", "$1_asdf").unwrap();
-        assert_eq!("foo_asdf", r.process("foo"));
-        assert_eq!("asd23$1_asdf", r.process("asd23$1"));
+        assert_eq!(Some("foo_asdf".to_string()), r.process("foo"));
+        assert_eq!(Some("asd23$1_asdf".to_string()), r.process("asd23$1"));
 
         let r = Renamer::new("foo(\\d+)", "$1_foo").unwrap();
-        assert_eq!("1_foo", r.process("foo1"));
-        assert_eq!("345_foo", r.process("foo345"));
-        assert_eq!("$1_foo", r.process("foo"));
-        assert_eq!("$1_foo", r.process("1234"));
+        assert_eq!(Some("1_foo".to_string()), r.process("foo1"));
+        assert_eq!(Some("345_foo".to_string()), r.process("foo345"));
+        assert_eq!(None, r.process("foo"));
+        assert_eq!(None, r.process("1234"));
+
+        let r = Renamer::new(r"E(?P<ep>\d+)", "Episode ${ep|pad:3}").unwrap();
+        assert_eq!(Some("Episode 007".to_string()), r.process("S01E7"));
+
+        let r = Renamer::new(r"E(?P<ep>\d+)", "E${ep + offset|pad:2}")
+            .unwrap()
+            .with_variables(Variables { offset: -25 });
+        assert_eq!(Some("E01".to_string()), r.process("S01E26"));
+    }
+
+    #[test]
+    fn rules_work() {
+        let rules = [
+            RenameRule::new(r"NC(OP|ED)(\d*)", "${1|lower}${2}", "", false).unwrap(),
+            RenameRule::new(r"- (\d+)", "E$1", r"^\[", true).unwrap(),
+            RenameRule::new(r"E(\d+)", "S01E${1|pad:2}", "", false).unwrap(),
+        ];
+        assert_eq!(Some("op2".to_string()), process_rules(&rules, "NCOP2"));
+        assert_eq!(
+            Some("S01E05".to_string()),
+            process_rules(&rules, "[Grp] Show - 5")
+        );
+        assert_eq!(Some("S01E07".to_string()), process_rules(&rules, "Show E7"));
+        assert_eq!(None, process_rules(&rules, "Show - 5"));
+
+        // chained rules that don't match pass the value through
+        let rules = [
+            RenameRule::new(r"(.+)", "$1!", "", true).unwrap(),
+            RenameRule::new(r"nope", "x", "", true).unwrap(),
+            RenameRule::new(r"(.+)", "<$1>", "", false).unwrap(),
+        ];
+        assert_eq!(Some("<a!>".to_string()), process_rules(&rules, "a"));
+        assert_eq!(None, process_rules(&[], "a"));
+    }
+
+    #[test]
+    fn reports_errors() {
+        assert_eq!(
+            Some("unclosed group".to_string()),
+            Renamer::new("(.+", "$1").err()
+        );
+        assert_eq!(
+            Some("unknown capture `2`".to_string()),
+            Renamer::new("(.+)", "$2").err()
+        );
+        assert_eq!(
+            Some("filter: unclosed group".to_string()),
+            RenameRule::new("(.+)", "$1", "(", false).err()
+        );
     }
 }
@@ -0,0 +1,130 @@
+use crate::{
+    movie::is_extras_folder,
+    path_utils::{file_name, split_ext},
+};
+
+// Keyword rules that route specials away from the regular episodes, e.g. `SP=S00` or
+// `NCOP=Featurettes`, comma separated. `S00` makes them Season 00 episodes, anything else
+// names the Plex extras folder they go to. Either way their own number is kept.
+pub const DEFAULT_RULES: &str =
+    "SP=S00, OVA=S00, OAD=S00, Special=S00, NCOP=Featurettes, NCED=Featurettes, Menu=Other, \
+     PV=Other, CM=Other";
+
+#[derive(Clone, Debug, Default)]
+pub struct SpecialRules {
+    rules: Vec<(String, Target)>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Target {
+    Season00,
+    ExtrasFolder(String),
+}
+
+impl SpecialRules {
+    pub fn parse(s: &str) -> Result<SpecialRules, String> {
+        let mut rules = vec![];
+        for rule in s.split(',').map(str::trim).filter(|rule| !rule.is_empty()) {
+            let (keyword, target) = rule
+                .split_once('=')
+                .ok_or_else(|| format!("`{}` should be `keyword=target`", rule))?;
+            let (keyword, target) = (keyword.trim(), target.trim());
+            if keyword.is_empty() || keyword.contains(|c: char| !c.is_alphanumeric()) {
+                return Err(format!("`{}` isn't a keyword", keyword));
+            }
+            let target = if target.eq_ignore_ascii_case("S00") {
+                Target::Season00
+            } else if is_extras_folder(target) {
+                Target::ExtrasFolder(target.to_string())
+            } else {
+                return Err(format!("`{}` isn't S00 or an extras folder", target));
+            };
+            rules.push((keyword.to_string(), target));
+        }
+        Ok(SpecialRules { rules })
+    }
+
+    // where the special at `path` goes in the root dir of `show`, if it is one, e.g.
+    // `[Group] Show - NCOP2 (BD).mkv` -> `Featurettes/NCOP 2.mkv`, or
+    // `Show - SP01.mkv` -> `Season 00/Show - S00E01 - SP 01.mkv`
+    pub fn route(&self, show: &str, path: &str) -> Option<String> {
+        let (stem, ext) = split_ext(path);
+        let ext = ext.map(|ext| format!(".{}", ext)).unwrap_or_default();
+        let words: Vec<_> = file_name(stem)
+            .split(|c: char| " ._-[](){}".contains(c))
+            .filter(|word| !word.is_empty())
+            .collect();
+
+        let (keyword, target, num) = self.rules.iter().find_map(|(keyword, target)| {
+            words.iter().enumerate().find_map(|(idx, word)| {
+                let rest = strip_prefix_ignore_case(word, keyword)?;
+                let num = if !rest.is_empty() {
+                    rest
+                } else {
+                    // e.g. `SP 01`
+                    words
+                        .get(idx + 1)
+                        .copied()
+                        .filter(|next| is_number(next))
+                        .unwrap_or("")
+                };
+                (num.is_empty() || is_number(num)).then_some((keyword, target, num))
+            })
+        })?;
+
+        let title = match num {
+            "" => keyword.clone(),
+            num => format!("{} {}", keyword, num),
+        };
+        Some(match target {
+            Target::Season00 => {
+                let episode: u32 = num.parse().unwrap_or(1);
+                format!("Season 00/{} - S00E{:02} - {}{}", show, episode, title, ext)
+            }
+            Target::ExtrasFolder(folder) => format!("{}/{}{}", folder, title, ext),
+        })
+    }
+}
+
+fn strip_prefix_ignore_case<'a>(word: &'a str, prefix: &str) -> Option<&'a str> {
+    let head = word.get(..prefix.len())?;
+    head.eq_ignore_ascii_case(prefix)
+        .then(|| &word[prefix.len()..])
+}
+
+fn is_number(s: &str) -> bool {
+    !s.is_empty() && s.len() <= 3 && s.chars().all(|c| c.is_ascii_digit())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{SpecialRules, DEFAULT_RULES};
+
+    #[test]
+    fn routes_specials() {
+        let rules = SpecialRules::parse(DEFAULT_RULES).unwrap();
+        let route = |path| rules.route("Shirokuma Cafe", path);
+        assert_eq!(
+            Some("Featurettes/NCOP 2.mkv".to_string()),
+            route("[Orphan] Shirokuma Cafe - NCOP2 (BD 720p) [35A192D6].mkv")
+        );
+        assert_eq!(
+            Some("Season 00/Shirokuma Cafe - S00E01 - SP 01.mkv".to_string()),
+            route("Shirokuma Cafe - SP 01.mkv")
+        );
+        assert_eq!(
+            Some("Season 00/Shirokuma Cafe - S00E03 - OVA 3.en.srt".to_string()),
+            route("Subs/Shirokuma.Cafe.OVA3.en.srt")
+        );
+        assert_eq!(
+            Some("Other/Menu.mkv".to_string()),
+            route("[Orphan] Shirokuma Cafe - Menu (BD 720p).mkv")
+        );
+        assert_eq!(None, route("[Orphan] Shirokuma Cafe - 01v2 (BD 720p).mkv"));
+        assert_eq!(None, route("Spider.Man.mkv"));
+
+        assert!(SpecialRules::parse("SP").is_err());
+        assert!(SpecialRules::parse("SP=Season 1").is_err());
+        assert!(SpecialRules::parse("").is_ok());
+    }
+}
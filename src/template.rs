@@ -0,0 +1,406 @@
+use regex::{Captures, Regex};
+
+// A parsed replacer template. Supported forms:
+//  $$                - a literal `$`
+//  $0, $1, $12       - positional capture (all digits are consumed, `$0` is the whole match)
+//  ${12}, ${ep}      - positional or named capture, braces disambiguate `${1}0` from `$10`
+//  ${ep - 25}        - integer arithmetic (`+`, `-`) on named captures, `$1`-style captures,
+//                      numbers and variables (see `Variables`)
+//  ${ep|pad:2|trim}  - a capture or expression passed through one or more filters, left to right
+// Any other `$` is kept as a literal.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Template {
+    segments: Vec<Segment>,
+}
+
+// values a template can reference by name, in addition to the finder's named captures. Captures
+// can't be named after them.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Variables {
+    pub offset: i64,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Segment {
+    Literal(String),
+    Capture {
+        group: usize,
+        filters: Vec<Filter>,
+    },
+    Sum {
+        terms: Vec<(i64, Operand)>,
+        filters: Vec<Filter>,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Operand {
+    Capture(usize),
+    Number(i64),
+    Offset,
+}
+
+// wider pads are typos, and would allocate that much for every file as it's typed
+const MAX_PAD_WIDTH: usize = 10;
+
+#[derive(Clone, Debug, PartialEq)]
+enum Filter {
+    Pad(usize),
+    Upper,
+    Lower,
+    Trim,
+    TitleCase,
+    Replace { from: String, to: String },
+}
+
+impl Template {
+    pub fn parse(finder: &Regex, template: &str) -> Result<Template, String> {
+        // `${offset}` would silently mean the variable rather than the capture
+        if let Some(name) = finder
+            .capture_names()
+            .flatten()
+            .find(|name| is_variable(name))
+        {
+            return Err(format!("capture name `{}` is reserved", name));
+        }
+
+        let mut segments = vec![];
+        let mut literal = String::new();
+        let mut rest = template;
+
+        while let Some(dollar) = rest.find('$') {
+            literal.push_str(&rest[..dollar]);
+            rest = &rest[dollar + 1..];
+
+            if let Some(after) = rest.strip_prefix('$') {
+                literal.push('$');
+                rest = after;
+                continue;
+            }
+
+            let (segment, after) = if let Some(braced) = rest.strip_prefix('{') {
+                let close = braced
+                    .find('}')
+                    .ok_or_else(|| "unclosed `${`".to_string())?;
+                let mut parts = braced[..close].split('|');
+                let expr = parts.next().unwrap();
+                let filters = parts.map(Filter::parse).collect::<Result<Vec<_>, _>>()?;
+                let segment = parse_expr(finder, expr, filters)?;
+                (segment, &braced[close + 1..])
+            } else {
+                let num_digits = rest
+                    .find(|c: char| !c.is_ascii_digit())
+                    .unwrap_or(rest.len());
+                if num_digits == 0 {
+                    literal.push('$');
+                    continue;
+                }
+                let segment = Segment::Capture {
+                    group: resolve_group(finder, &rest[..num_digits])?,
+                    filters: vec![],
+                };
+                (segment, &rest[num_digits..])
+            };
+
+            if !literal.is_empty() {
+                segments.push(Segment::Literal(std::mem::take(&mut literal)));
+            }
+            segments.push(segment);
+            rest = after;
+        }
+
+        literal.push_str(rest);
+        if !literal.is_empty() {
+            segments.push(Segment::Literal(literal));
+        }
+        Ok(Template { segments })
+    }
+
+    // None when a sum can't be computed, e.g. `${ep - 1}` for `SP`, rather than putting the
+    // expression itself in the name
+    pub fn render(&self, captures: &Captures, variables: &Variables) -> Option<String> {
+        let mut rendered = String::new();
+        for segment in self.segments.iter() {
+            match segment {
+                Segment::Literal(lit) => rendered.push_str(lit),
+                Segment::Capture { group, filters } => {
+                    let value = captures.get(*group).map_or("", |cap| cap.as_str());
+                    rendered.push_str(&apply_filters(filters, value.to_string()));
+                }
+                Segment::Sum { terms, filters } => {
+                    let value = eval_sum(terms, captures, variables)?;
+                    rendered.push_str(&apply_filters(filters, value.to_string()));
+                }
+            }
+        }
+        Some(rendered)
+    }
+}
+
+fn apply_filters(filters: &[Filter], value: String) -> String {
+    filters
+        .iter()
+        .fold(value, |value, filter| filter.apply(value))
+}
+
+// A lone number is a positional capture (`${12}`), anything else is parsed as a sum
+fn parse_expr(finder: &Regex, expr: &str, filters: Vec<Filter>) -> Result<Segment, String> {
+    let trimmed = expr.trim();
+    if !trimmed.contains(['+', '-', '$']) && !is_variable(trimmed) {
+        return Ok(Segment::Capture {
+            group: resolve_group(finder, trimmed)?,
+            filters,
+        });
+    }
+
+    let mut terms = vec![];
+    let mut sign = 1;
+    let mut expect_operand = true;
+    let mut rest = trimmed;
+    while !rest.is_empty() {
+        let token_len = match rest.find(|c: char| !(c.is_alphanumeric() || c == '_')) {
+            Some(0) => rest.chars().next().unwrap().len_utf8(),
+            Some(len) => len,
+            None => rest.len(),
+        };
+        let token = &rest[..token_len];
+        rest = rest[token_len..].trim_start();
+
+        match (expect_operand, token) {
+            (true, "$") => {
+                let num_digits = rest
+                    .find(|c: char| !c.is_ascii_digit())
+                    .unwrap_or(rest.len());
+                let group = resolve_group(finder, &rest[..num_digits])?;
+                rest = rest[num_digits..].trim_start();
+                terms.push((sign, Operand::Capture(group)));
+                expect_operand = false;
+            }
+            (true, "-") => sign = -sign,
+            (true, "+") => {}
+            (true, _) => {
+                let operand = if let Ok(num) = token.parse() {
+                    Operand::Number(num)
+                } else if is_variable(token) {
+                    Operand::Offset
+                } else {
+                    Operand::Capture(resolve_group(finder, token)?)
+                };
+                terms.push((sign, operand));
+                expect_operand = false;
+            }
+            (false, "+") => {
+                sign = 1;
+                expect_operand = true;
+            }
+            (false, "-") => {
+                sign = -1;
+                expect_operand = true;
+            }
+            (false, _) => return Err(format!("unexpected `{}` in `{}`", token, trimmed)),
+        }
+    }
+
+    if expect_operand {
+        return Err(format!("incomplete expression `{}`", trimmed));
+    }
+
+    Ok(Segment::Sum { terms, filters })
+}
+
+fn is_variable(name: &str) -> bool {
+    name == "offset"
+}
+
+fn eval_sum(terms: &[(i64, Operand)], captures: &Captures, variables: &Variables) -> Option<i64> {
+    terms.iter().try_fold(0i64, |acc, (sign, operand)| {
+        let value = match operand {
+            Operand::Capture(group) => captures.get(*group)?.as_str().parse::<i64>().ok()?,
+            Operand::Number(num) => *num,
+            Operand::Offset => variables.offset,
+        };
+        acc.checked_add(sign.checked_mul(value)?)
+    })
+}
+
+fn resolve_group(finder: &Regex, group: &str) -> Result<usize, String> {
+    let group = group.trim();
+    let idx = if let Ok(idx) = group.parse::<usize>() {
+        Some(idx).filter(|idx| *idx < finder.captures_len())
+    } else {
+        finder.capture_names().position(|name| name == Some(group))
+    };
+    idx.ok_or_else(|| format!("unknown capture `{}`", group))
+}
+
+impl Filter {
+    fn parse(filter: &str) -> Result<Filter, String> {
+        let (name, arg) = match filter.split_once(':') {
+            Some((name, arg)) => (name.trim(), Some(arg)),
+            None => (filter.trim(), None),
+        };
+
+        match (name, arg) {
+            ("pad", Some(width)) => width
+                .trim()
+                .parse()
+                .ok()
+                .filter(|width| *width <= MAX_PAD_WIDTH)
+                .map(Filter::Pad)
+                .ok_or_else(|| format!("invalid pad width `{}`", width)),
+            ("upper", None) => Ok(Filter::Upper),
+            ("lower", None) => Ok(Filter::Lower),
+            ("trim", None) => Ok(Filter::Trim),
+            ("title_case", None) => Ok(Filter::TitleCase),
+            ("replace", Some(arg)) => match arg.split_once(':') {
+                Some((from, to)) if !from.is_empty() => Ok(Filter::Replace {
+                    from: from.to_string(),
+                    to: to.to_string(),
+                }),
+                _ => Err("expected `replace:from:to`".to_string()),
+            },
+            _ => Err(format!("unknown filter `{}`", filter)),
+        }
+    }
+
+    fn apply(&self, value: String) -> String {
+        match self {
+            Filter::Pad(width) => match value.parse::<u64>() {
+                Ok(num) => format!("{:0width$}", num, width = width),
+                Err(_) => format!("{:0>width$}", value, width = width),
+            },
+            Filter::Upper => value.to_uppercase(),
+            Filter::Lower => value.to_lowercase(),
+            Filter::Trim => value.trim().to_string(),
+            Filter::TitleCase => title_case(&value),
+            Filter::Replace { from, to } => value.replace(from.as_str(), to),
+        }
+    }
+}
+
+fn title_case(value: &str) -> String {
+    let mut ret = String::with_capacity(value.len());
+    let mut word_start = true;
+    for c in value.chars() {
+        if word_start {
+            ret.extend(c.to_uppercase());
+        } else {
+            ret.extend(c.to_lowercase());
+        }
+        word_start = c.is_whitespace();
+    }
+    ret
+}
+
+#[cfg(test)]
+mod test {
+    use regex::Regex;
+
+    use super::{Template, Variables};
+
+    fn render(finder: &str, template: &str, input: &str) -> String {
+        render_with_offset(finder, template, input, 0).unwrap()
+    }
+
+    fn render_with_offset(
+        finder: &str,
+        template: &str,
+        input: &str,
+        offset: i64,
+    ) -> Option<String> {
+        let finder = Regex::new(finder).unwrap();
+        let template = Template::parse(&finder, template).unwrap();
+        template.render(&finder.captures(input).unwrap(), &Variables { offset })
+    }
+
+    fn parse_err(finder: &str, template: &str) -> String {
+        Template::parse(&Regex::new(finder).unwrap(), template).unwrap_err()
+    }
+
+    #[test]
+    fn captures_work() {
+        assert_eq!(
+            "05 of 03",
+            render(r"S(?P<s>\d+)E(?P<ep>\d+)", "${ep} of ${s}", "S03E05")
+        );
+        assert_eq!("10 2", render(r"(\d)(\d)", "${1}0 $2", "12"));
+        assert_eq!("[][b]", render(r"(a)?(b)", "[$1][$2]", "b"));
+        assert_eq!("<123>", render(r"\d+", "<$0>", "abc123"));
+        assert_eq!("$1 $foo $x", render("(.+)", "$$1 $$$1 $x", "foo"));
+    }
+
+    #[test]
+    fn filters_work() {
+        assert_eq!("E05", render(r"(\d+)", "E${1|pad:2}", "5"));
+        assert_eq!("E05", render(r"(\d+)", "E${1|pad:2}", "005"));
+        assert_eq!("E123", render(r"(\d+)", "E${1|pad:2}", "123"));
+        assert_eq!("0ab", render(r"(\w+)", "${1|pad:3}", "ab"));
+        assert_eq!("FOO", render(r"(\w+)", "${1|upper}", "foo"));
+        assert_eq!("foo", render(r"(\w+)", "${1 | lower}", "FOO"));
+        assert_eq!("[foo]", render(r"_(.+)_", "[${1|trim}]", "_ foo _"));
+        assert_eq!(
+            "Three Robots Exit Strategies",
+            render(
+                r"(?P<title>.+)",
+                "${title|replace:.: |title_case}",
+                "three.robots.EXIT.strategies"
+            )
+        );
+    }
+
+    #[test]
+    fn arithmetic_works() {
+        let finder = r"- (?P<ep>\d+)";
+        assert_eq!("E01", render(finder, "E${ep - 25|pad:2}", "Cafe - 26"));
+        assert_eq!("E-2", render(finder, "E${ep-27}", "Cafe - 25"));
+        assert_eq!("E30", render(finder, "E${ep + 5}", "Cafe - 25"));
+        assert_eq!("E27", render(finder, "E${$1 + 2}", "Cafe - 25"));
+        assert_eq!("E27", render(finder, "E${ep - -2}", "Cafe - 25"));
+        assert_eq!(
+            Some("E01".to_string()),
+            render_with_offset(finder, "E${ep + offset|pad:2}", "Cafe - 26", -25)
+        );
+        assert_eq!(
+            Some("-25".to_string()),
+            render_with_offset(finder, "${offset}", "Cafe - 26", -25)
+        );
+
+        // non-numeric operands and overflows fail the whole name
+        assert_eq!(
+            None,
+            render_with_offset(r"(?P<ep>\w+)", "E${ep - 1}", "SP", 0)
+        );
+        assert_eq!(
+            None,
+            render_with_offset(finder, "E${ep - offset}", "Cafe - 1", i64::MIN)
+        );
+        assert_eq!(
+            None,
+            render_with_offset(r"(?P<ep>\d+)", "${ep + 1}", &i64::MAX.to_string(), 0)
+        );
+    }
+
+    #[test]
+    fn reports_errors() {
+        assert_eq!("unknown capture `2`", parse_err("(.+)", "$2"));
+        assert_eq!("unknown capture `ep`", parse_err("(.+)", "${ep}"));
+        assert_eq!("unclosed `${`", parse_err("(.+)", "${1"));
+        assert_eq!("unknown filter `shout`", parse_err("(.+)", "${1|shout}"));
+        assert_eq!("invalid pad width `x`", parse_err("(.+)", "${1|pad:x}"));
+        assert_eq!(
+            "invalid pad width `999999999`",
+            parse_err("(.+)", "${1|pad:999999999}")
+        );
+        assert_eq!(
+            "expected `replace:from:to`",
+            parse_err("(.+)", "${1|replace:x}")
+        );
+        assert_eq!("unknown capture `ep`", parse_err("(.+)", "${ep + 1}"));
+        assert_eq!("incomplete expression `1 -`", parse_err("(.+)", "${1 -}"));
+        assert_eq!("unexpected `2` in `$1 2`", parse_err("(.+)", "${$1 2}"));
+        assert_eq!(
+            "capture name `offset` is reserved",
+            parse_err(r"(?P<offset>\d+)", "$1")
+        );
+    }
+}
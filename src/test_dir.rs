@@ -0,0 +1,45 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+// A scratch dir under the system temp dir for tests that touch disk. It's removed when dropped,
+// so it doesn't outlive a failing test either.
+pub struct TestDir {
+    root: PathBuf,
+}
+
+impl TestDir {
+    pub fn new(name: &str) -> TestDir {
+        let root = std::env::temp_dir().join(format!("renamer-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        TestDir { root }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    // `name` in the dir, as the string paths the app works with
+    pub fn path(&self, name: &str) -> String {
+        self.root.join(name).to_string_lossy().to_string()
+    }
+
+    pub fn create_dir(&self, name: &str) {
+        fs::create_dir_all(self.root.join(name)).unwrap();
+    }
+
+    // creates the file at `name` along with its parent dirs
+    pub fn write(&self, name: &str, contents: impl AsRef<[u8]>) {
+        let path = self.root.join(name);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, contents).unwrap();
+    }
+}
+
+impl Drop for TestDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.root);
+    }
+}
@@ -2,17 +2,25 @@ use tui::{
     backend::Backend,
     layout::{Constraint, Direction, Layout, Rect},
     style::{Color, Modifier, Style},
-    text::Span,
-    widgets::{Block, Borders, Cell, Clear, List, ListItem, Row, Table, TextInput, TextInputState},
+    text::{Span, Spans},
+    widgets::{
+        Block, Borders, Cell, Clear, List, ListItem, ListState, Row, Table, TextInput,
+        TextInputState,
+    },
     Frame,
 };
 
 use crate::{
     app::App,
     app_state::{
-        configure_mapping_state::ConfigureMappingState,
-        mapping_state::{self, MappingState},
+        configure_mapping_state::{
+            ConfigureMappingState, FILTERED_TREE_DIR, JUNK_TREE_DIR, SUPERSEDED_TREE_DIR,
+            UNMATCHED_TREE_DIR,
+        },
+        mapping_state::{self, MappingState, RULE_FILTER, RULE_MATCHER, RULE_REPLACER},
     },
+    path_utils::{dir_name, file_name, join_path, resolve_parents},
+    widgets::tree_list::TreeList,
 };
 pub fn configure_mapping<B: Backend>(
     f: &mut Frame<B>,
@@ -42,14 +50,21 @@ pub fn configure_mapping<B: Backend>(
     };
 
     // compute main layout within the popup
+    let max_rule_lines = 6;
+    let num_rule_lines = configure_mapping_state
+        .mapped_dir
+        .file_rules()
+        .len()
+        .min(max_rule_lines) as u16;
     let main_layout = Layout::default()
         .direction(Direction::Vertical)
         .margin(1)
         .constraints(
             [
-                Constraint::Length(4), // status / input / output dir
-                Constraint::Length(9), // input configurations
-                Constraint::Min(1),    // file rename preview
+                Constraint::Length(6),  // status / input / output dir / link mode / kind
+                Constraint::Length(15), // input configurations
+                Constraint::Length(num_rule_lines + 2), // file rules
+                Constraint::Min(1),     // file rename preview
             ]
             .as_ref(),
         )
@@ -57,7 +72,8 @@ pub fn configure_mapping<B: Backend>(
 
     let status_rect = main_layout[0];
     let config_rect = main_layout[1];
-    let file_preview_rect = main_layout[2];
+    let rules_rect = main_layout[2];
+    let file_preview_rect = main_layout[3];
 
     // render status rect
     {
@@ -67,39 +83,61 @@ pub fn configure_mapping<B: Backend>(
             .get(configure_mapping_state.mapping_idx)
             .unwrap();
 
-        let status_span = match existing_mapping {
-            MappingState::HasMapping { mapped_dir } => {
-                if mapped_dir.configs_eq(&configure_mapping_state.mapped_dir) {
-                    Span::styled(
-                        "Saved",
-                        Style::default()
-                            .fg(Color::LightGreen)
-                            .add_modifier(Modifier::BOLD),
-                    )
-                } else {
-                    Span::styled(
-                        "Changed",
-                        Style::default()
-                            .fg(Color::LightYellow)
-                            .add_modifier(Modifier::BOLD),
-                    )
+        let status_span = if let Some(error) = configure_mapping_state.mapped_dir.config_error() {
+            Span::styled(
+                error,
+                Style::default().fg(Color::Red).add_modifier(Modifier::BOLD),
+            )
+        } else {
+            match existing_mapping {
+                MappingState::HasMapping { mapped_dir } => {
+                    if mapped_dir.configs_eq(&configure_mapping_state.mapped_dir) {
+                        Span::styled(
+                            "Saved",
+                            Style::default()
+                                .fg(Color::LightGreen)
+                                .add_modifier(Modifier::BOLD),
+                        )
+                    } else {
+                        Span::styled(
+                            "Changed",
+                            Style::default()
+                                .fg(Color::LightYellow)
+                                .add_modifier(Modifier::BOLD),
+                        )
+                    }
                 }
+                MappingState::Unmapped { in_path: _ } => Span::styled(
+                    "New",
+                    Style::default()
+                        .fg(Color::LightBlue)
+                        .add_modifier(Modifier::ITALIC | Modifier::BOLD),
+                ),
             }
-            MappingState::Unmapped { in_path: _ } => Span::styled(
-                "New",
-                Style::default()
-                    .fg(Color::LightBlue)
-                    .add_modifier(Modifier::ITALIC | Modifier::BOLD),
-            ),
         };
 
         let in_path_span = Span::styled(
             configure_mapping_state.mapped_dir.in_dir_name(),
             Style::default().add_modifier(Modifier::BOLD),
         );
-        let out_path_span = match configure_mapping_state.mapped_dir.out_dir_name() {
-            Some(out_path) => Span::styled(out_path, Style::default().add_modifier(Modifier::BOLD)),
-            None => Span::styled("error", Style::default().fg(Color::Red)),
+        let out_path_spans = match configure_mapping_state.mapped_dir.out_dir_name() {
+            Some(out_path) => {
+                let shared_with = app
+                    .selecting_input_state
+                    .mappings_sharing_out_dir(configure_mapping_state.mapping_idx, &out_path);
+                let mut spans = vec![Span::styled(
+                    out_path,
+                    Style::default().add_modifier(Modifier::BOLD),
+                )];
+                if !shared_with.is_empty() {
+                    spans.push(Span::styled(
+                        format!("  shared with {}", shared_with.join(", ")),
+                        Style::default().fg(Color::Yellow),
+                    ));
+                }
+                Spans::from(spans)
+            }
+            None => Spans::from(Span::styled("error", Style::default().fg(Color::Red))),
         };
 
         let table = Table::new(vec![
@@ -113,7 +151,33 @@ pub fn configure_mapping<B: Backend>(
             ]),
             Row::new(vec![
                 Cell::from(Span::raw("Output Dir")),
-                Cell::from(out_path_span),
+                Cell::from(out_path_spans),
+            ]),
+            Row::new(vec![
+                Cell::from(Span::raw("Link Mode")),
+                Cell::from(Spans::from(vec![
+                    Span::styled(
+                        configure_mapping_state.mapped_dir.link_mode().as_str(),
+                        Style::default().add_modifier(Modifier::BOLD),
+                    ),
+                    Span::styled(
+                        "  m change",
+                        Style::default().add_modifier(Modifier::ITALIC),
+                    ),
+                ])),
+            ]),
+            Row::new(vec![
+                Cell::from(Span::raw("Kind")),
+                Cell::from(Spans::from(vec![
+                    Span::styled(
+                        configure_mapping_state.mapped_dir.media_kind().as_str(),
+                        Style::default().add_modifier(Modifier::BOLD),
+                    ),
+                    Span::styled(
+                        "  k change",
+                        Style::default().add_modifier(Modifier::ITALIC),
+                    ),
+                ])),
             ]),
         ])
         .widths([Constraint::Length(16), Constraint::Length(100)].as_ref());
@@ -126,9 +190,11 @@ pub fn configure_mapping<B: Backend>(
             .direction(Direction::Vertical)
             .constraints(
                 [
-                    Constraint::Length(3), // 0 - file ext filter
-                    Constraint::Length(3), // 1 - dir matcher / replacer
-                    Constraint::Length(3), // 2 - file matcher / replacer
+                    Constraint::Length(3), // 0 - file ext filter / offset
+                    Constraint::Length(3), // 1 - specials / junk
+                    Constraint::Length(3), // 2 - dir matcher / replacer
+                    Constraint::Length(3), // 3 - file matcher / replacer / rule filter
+                    Constraint::Length(3), // 4 - example output
                     Constraint::Min(1),    // x - rest of padding
                 ]
                 .as_ref(),
@@ -136,18 +202,35 @@ pub fn configure_mapping<B: Backend>(
             .split(config_rect);
 
         let config_input_rects = vec![
-            // file ext filter
-            vec![config_parent_layout[0]],
-            // dir matcher / replacer
+            // file ext filter / offset
+            Layout::default()
+                .direction(Direction::Horizontal)
+                .constraints([Constraint::Min(1), Constraint::Length(16)])
+                .split(config_parent_layout[0]),
+            // specials / junk
             Layout::default()
                 .direction(Direction::Horizontal)
                 .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
                 .split(config_parent_layout[1]),
-            // file matcher / replacer
+            // dir matcher / replacer
             Layout::default()
                 .direction(Direction::Horizontal)
                 .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
                 .split(config_parent_layout[2]),
+            // file matcher / replacer / rule filter
+            Layout::default()
+                .direction(Direction::Horizontal)
+                .constraints([
+                    Constraint::Percentage(40),
+                    Constraint::Percentage(40),
+                    Constraint::Percentage(20),
+                ])
+                .split(config_parent_layout[3]),
+            // example output
+            Layout::default()
+                .direction(Direction::Horizontal)
+                .constraints([Constraint::Percentage(100)])
+                .split(config_parent_layout[4]),
         ];
 
         let input_block = |f: &mut Frame<B>,
@@ -186,36 +269,129 @@ pub fn configure_mapping<B: Backend>(
             "File Types",
             mapped_dir.has_valid_file_filter(),
         );
+        input_block(
+            f,
+            config_input_rects[0][1],
+            &configure_mapping_state.form.offset_input_state,
+            "Offset",
+            mapped_dir.has_valid_offset(),
+        );
         input_block(
             f,
             config_input_rects[1][0],
+            &configure_mapping_state.form.specials_input_state,
+            "Specials",
+            mapped_dir.has_valid_specials(),
+        );
+        input_block(
+            f,
+            config_input_rects[1][1],
+            &configure_mapping_state.form.junk_input_state,
+            "Junk",
+            mapped_dir.has_valid_junk_list(),
+        );
+        input_block(
+            f,
+            config_input_rects[2][0],
             &configure_mapping_state.form.dir_matcher_input_state,
             "Dir Matcher",
             mapped_dir.has_valid_dir_renamer(),
         );
         input_block(
             f,
-            config_input_rects[1][1],
+            config_input_rects[2][1],
             &configure_mapping_state.form.dir_replacer_input_state,
             "Dir Replacer",
             mapped_dir.has_valid_dir_renamer(),
         );
+        let selected_rule = configure_mapping_state.selected_rule;
+        let rule_is_valid = mapped_dir.has_valid_file_rule(selected_rule);
         input_block(
             f,
-            config_input_rects[2][0],
+            config_input_rects[3][0],
             &configure_mapping_state.form.file_matcher_input_state,
-            "File Matcher",
-            mapped_dir.has_valid_file_renamer(),
+            &format!("File Matcher - Rule {}", selected_rule + 1),
+            rule_is_valid,
         );
         input_block(
             f,
-            config_input_rects[2][1],
+            config_input_rects[3][1],
             &configure_mapping_state.form.file_replacer_input_state,
             "File Replacer",
-            mapped_dir.has_valid_file_renamer(),
+            rule_is_valid,
+        );
+        input_block(
+            f,
+            config_input_rects[3][2],
+            &configure_mapping_state.form.rule_filter_input_state,
+            "Rule Filter",
+            rule_is_valid,
+        );
+        let example_title = match (
+            &configure_mapping_state.example_error,
+            mapped_dir
+                .file_mappings()
+                .get(configure_mapping_state.selected_file),
+        ) {
+            (Some(error), _) => format!("Example Output - {}", error),
+            (None, Some(mapping)) => {
+                format!("Example Output - {} - enter to apply", mapping.name())
+            }
+            (None, None) => "Example Output".to_string(),
+        };
+        input_block(
+            f,
+            config_input_rects[4][0],
+            &configure_mapping_state.form.example_input_state,
+            &example_title,
+            configure_mapping_state.example_error.is_none(),
         );
     }
 
+    // render file rules rect
+    {
+        let mapped_dir = &configure_mapping_state.mapped_dir;
+        let rule_items: Vec<_> = mapped_dir
+            .file_rules()
+            .iter()
+            .enumerate()
+            .map(|(idx, rule)| {
+                let style = if mapped_dir.has_valid_file_rule(idx) {
+                    Style::default()
+                } else {
+                    Style::default().fg(Color::Red)
+                };
+                let mut text = format!(
+                    "{}. {}  ->  {}",
+                    idx + 1,
+                    rule.config(RULE_MATCHER),
+                    rule.config(RULE_REPLACER)
+                );
+                if !rule.config(RULE_FILTER).is_empty() {
+                    text += &format!("  if {}", rule.config(RULE_FILTER));
+                }
+                if rule.chains() {
+                    text += "  (chain)";
+                }
+                ListItem::new(Span::styled(text, style))
+            })
+            .collect();
+
+        let rules_list = List::new(rule_items)
+            .highlight_style(Style::default().bg(Color::Rgb(40, 40, 40)))
+            .block(Block::default().borders(Borders::ALL).title(vec![
+                Span::raw("File Rules - "),
+                Span::styled(
+                    "[ ] select, { } move, a add, d delete, c chain",
+                    Style::default().add_modifier(Modifier::ITALIC),
+                ),
+            ]));
+
+        let mut rules_state = ListState::default();
+        rules_state.select(Some(configure_mapping_state.selected_rule));
+        f.render_stateful_widget(rules_list, rules_rect, &mut rules_state);
+    }
+
     // render file preview rect
     {
         let file_preview_layout = Layout::default()
@@ -233,58 +409,126 @@ pub fn configure_mapping<B: Backend>(
             let files_list: Vec<_> = file_mappings
                 .iter()
                 .map(|mapping| {
-                    let span = match mapping {
+                    let (path, style) = match mapping {
                         mapping_state::FileMapping::MappedTo {
                             from_name: from_path,
                             to_name: _,
-                        } => Span::styled(from_path, Style::default().add_modifier(Modifier::BOLD)),
-                        mapping_state::FileMapping::Filtered { name: path } => Span::raw(path),
+                        } => (from_path, Style::default().add_modifier(Modifier::BOLD)),
+                        mapping_state::FileMapping::Filtered { name: path } => {
+                            (path, Style::default())
+                        }
+                        mapping_state::FileMapping::Unmatched { name: path } => {
+                            (path, Style::default().fg(Color::Red))
+                        }
+                        mapping_state::FileMapping::Superseded { name: path, .. } => (
+                            path,
+                            Style::default()
+                                .fg(Color::DarkGray)
+                                .add_modifier(Modifier::CROSSED_OUT),
+                        ),
+                        mapping_state::FileMapping::Junk { name: path } => (
+                            path,
+                            Style::default()
+                                .fg(Color::DarkGray)
+                                .add_modifier(Modifier::ITALIC),
+                        ),
                     };
-                    ListItem::new(span)
+                    // files in subdirs are indented under a dimmed copy of their dir
+                    let dir = dir_name(path);
+                    let mut spans = if dir.is_empty() {
+                        vec![Span::styled(path.as_str(), style)]
+                    } else {
+                        vec![
+                            Span::styled(format!("{}/", dir), Style::default().fg(Color::DarkGray)),
+                            Span::styled(file_name(path), style),
+                        ]
+                    };
+                    if configure_mapping_state.crc_mismatches.contains(path) {
+                        spans.push(Span::styled(
+                            "  crc mismatch",
+                            Style::default().fg(Color::Red).add_modifier(Modifier::BOLD),
+                        ));
+                    }
+                    if mapped_dir.is_kept_junk(path) {
+                        spans.push(Span::styled(
+                            "  kept junk",
+                            Style::default().add_modifier(Modifier::ITALIC),
+                        ));
+                    }
+                    ListItem::new(Spans::from(spans))
                 })
                 .collect();
 
-            let block = Block::default().borders(Borders::ALL).title(vec![
+            let mut title = vec![
                 Span::raw("Input Files - "),
                 Span::styled(
                     format!("{} ", files_list.len()),
                     Style::default().add_modifier(Modifier::BOLD),
                 ),
-            ]);
-            List::new(files_list).block(block)
+            ];
+            let num_mismatches = configure_mapping_state.crc_mismatches.len();
+            if num_mismatches > 0 {
+                title.push(Span::styled(
+                    format!("{} crc mismatches ", num_mismatches),
+                    Style::default().fg(Color::Red).add_modifier(Modifier::BOLD),
+                ));
+            }
+            title.push(Span::styled(
+                "j toggle junk",
+                Style::default().add_modifier(Modifier::ITALIC),
+            ));
+            let block = Block::default().borders(Borders::ALL).title(title);
+            List::new(files_list)
+                .highlight_style(Style::default().bg(Color::Rgb(40, 40, 40)))
+                .block(block)
         };
 
-        let out_file_list = {
-            let mut num_files = 0;
-            let files_list: Vec<_> = file_mappings
+        let out_tree = {
+            let num_files = file_mappings
                 .iter()
-                .map(|mapping| {
-                    let span = match mapping {
-                        mapping_state::FileMapping::MappedTo {
-                            from_name: _,
-                            to_name,
-                        } => {
-                            num_files += 1;
-                            Span::raw(to_name)
-                        }
-                        mapping_state::FileMapping::Filtered { name: _ } => {
-                            Span::styled("", Style::default().add_modifier(Modifier::ITALIC))
-                        }
-                    };
-                    ListItem::new(span)
-                })
-                .collect();
+                .filter(|mapping| matches!(mapping, mapping_state::FileMapping::MappedTo { .. }))
+                .count();
+            let out_dir_name = mapped_dir.out_dir_name().unwrap_or_default();
             let block = Block::default().borders(Borders::ALL).title(vec![
-                Span::raw("Output Files - "),
+                Span::raw("Output Tree - "),
                 Span::styled(
                     format!("{} ", num_files),
                     Style::default().add_modifier(Modifier::BOLD),
                 ),
+                Span::styled(
+                    "tab focus, ←→ collapse / expand",
+                    Style::default().add_modifier(Modifier::ITALIC),
+                ),
             ]);
-            List::new(files_list).block(block)
+            TreeList::default()
+                .block(block)
+                .dir_style(Style::default().fg(Color::Blue))
+                .highlight_style(Style::default().bg(Color::Rgb(40, 40, 40)))
+                .leaf_style(move |path| {
+                    let is_duplicate = mapped_dir
+                        .duplicate_targets()
+                        .iter()
+                        .any(|to_name| resolve_parents(&join_path(&out_dir_name, to_name)) == path);
+                    if [FILTERED_TREE_DIR, SUPERSEDED_TREE_DIR, JUNK_TREE_DIR]
+                        .iter()
+                        .any(|dir| path.starts_with(dir))
+                    {
+                        Style::default().fg(Color::DarkGray)
+                    } else if path.starts_with(UNMATCHED_TREE_DIR) || is_duplicate {
+                        Style::default().fg(Color::Red)
+                    } else {
+                        Style::default()
+                    }
+                })
         };
 
-        f.render_widget(in_file_list, in_file_rect);
-        f.render_widget(out_file_list, out_file_rect);
+        let mut files_state = ListState::default();
+        files_state.select(Some(configure_mapping_state.selected_file));
+        f.render_stateful_widget(in_file_list, in_file_rect, &mut files_state);
+        f.render_interactive(
+            out_tree,
+            out_file_rect,
+            &configure_mapping_state.form.output_tree_state,
+        );
     }
 }
@@ -0,0 +1,130 @@
+use tui::{
+    backend::Backend,
+    layout::{Constraint, Direction, Layout},
+    style::{Color, Modifier, Style},
+    text::{Span, Spans},
+    widgets::{Block, Borders, Clear, List, ListItem, Paragraph},
+    Frame,
+};
+
+use crate::app_state::confirm_plan_state::ConfirmPlanState;
+
+pub fn confirm_plan<B: Backend>(f: &mut Frame<B>, confirm_plan_state: &ConfirmPlanState) {
+    // render parent popup + clear background
+    let popup_rect = {
+        let popup_rect = Layout::default()
+            .direction(Direction::Horizontal)
+            .horizontal_margin(16)
+            .vertical_margin(4)
+            .constraints([Constraint::Percentage(100)].as_ref())
+            .split(f.size())[0];
+
+        let block = Block::default()
+            .title(Span::styled(
+                "Apply Mapping",
+                Style::default().add_modifier(Modifier::BOLD),
+            ))
+            .borders(Borders::ALL);
+
+        let popup_rect_inner = block.inner(popup_rect);
+        f.render_widget(Clear, popup_rect);
+        f.render_widget(block, popup_rect);
+        popup_rect_inner
+    };
+
+    let plan = &confirm_plan_state.plan;
+    let max_conflict_lines = 6;
+    let num_conflict_lines = plan.conflicts().len().min(max_conflict_lines) as u16;
+    let main_layout = Layout::default()
+        .direction(Direction::Vertical)
+        .margin(1)
+        .constraints(
+            [
+                Constraint::Length(3),                  // summary / keys / sharing
+                Constraint::Length(num_conflict_lines), // conflicts
+                Constraint::Min(1),                     // operations
+            ]
+            .as_ref(),
+        )
+        .split(popup_rect);
+
+    // render summary rect
+    {
+        let is_shared = !plan.shared_with().is_empty();
+        let keys_span = if !plan.conflicts().is_empty() {
+            Span::styled(
+                "resolve conflicts to apply, Esc back",
+                Style::default()
+                    .fg(Color::Red)
+                    .add_modifier(Modifier::ITALIC),
+            )
+        } else if !confirm_plan_state.can_apply() {
+            Span::styled(
+                "m allow merging into the shared output dir, Esc back",
+                Style::default()
+                    .fg(Color::Yellow)
+                    .add_modifier(Modifier::ITALIC),
+            )
+        } else if is_shared {
+            Span::styled(
+                "Enter apply, m disallow merging, Esc back",
+                Style::default().add_modifier(Modifier::ITALIC),
+            )
+        } else {
+            Span::styled(
+                "Enter apply, Esc back",
+                Style::default().add_modifier(Modifier::ITALIC),
+            )
+        };
+
+        let mut lines = vec![
+            Spans::from(Span::styled(
+                plan.summary(),
+                Style::default().add_modifier(Modifier::BOLD),
+            )),
+            Spans::from(keys_span),
+        ];
+        if is_shared {
+            lines.push(Spans::from(Span::styled(
+                format!("output dir shared with {}", plan.shared_with().join(", ")),
+                Style::default().fg(Color::Yellow),
+            )));
+        }
+        let summary = Paragraph::new(lines);
+        f.render_widget(summary, main_layout[0]);
+    }
+
+    // render conflicts rect
+    {
+        let conflict_items: Vec<_> = plan
+            .conflicts()
+            .iter()
+            .map(|conflict| {
+                ListItem::new(Span::styled(
+                    format!("conflict: {}", conflict),
+                    Style::default().fg(Color::Red),
+                ))
+            })
+            .collect();
+        f.render_widget(List::new(conflict_items), main_layout[1]);
+    }
+
+    // render operations rect
+    {
+        let operation_items: Vec<_> = plan
+            .operations()
+            .iter()
+            .skip(confirm_plan_state.scroll)
+            .map(|operation| ListItem::new(Span::raw(operation.to_string())))
+            .collect();
+        let operations_list =
+            List::new(operation_items).block(Block::default().borders(Borders::ALL).title(vec![
+                Span::raw("Operations - "),
+                Span::styled(
+                    format!("{} ", plan.operations().len()),
+                    Style::default().add_modifier(Modifier::BOLD),
+                ),
+            ]));
+        f.render_widget(operations_list, main_layout[2]);
+    }
+}
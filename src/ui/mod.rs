@@ -1,5 +1,7 @@
 mod configure_mapping;
+mod confirm_plan;
 mod selecting_input;
 
 pub use configure_mapping::configure_mapping;
+pub use confirm_plan::confirm_plan;
 pub use selecting_input::selecting_input;
@@ -85,9 +85,19 @@ pub fn selecting_input<B: Backend>(f: &mut Frame<B>, app: &App, active: bool) {
 
     let output_items: Vec<ListItem> = mappings
         .iter()
-        .map(|mapping| {
+        .enumerate()
+        .map(|(idx, mapping)| {
             let span = match mapping {
                 MappingState::HasMapping { mapped_dir } => match mapped_dir.out_dir_name() {
+                    // output dirs claimed by more than one input
+                    Some(out_path)
+                        if !app
+                            .selecting_input_state
+                            .mappings_sharing_out_dir(idx, &out_path)
+                            .is_empty() =>
+                    {
+                        Span::styled(out_path, Style::default().fg(Color::Yellow))
+                    }
                     Some(out_path) => Span::raw(out_path),
                     None => Span::styled("error", Style::default().fg(Color::Red)),
                 },
@@ -1 +1 @@
-mod tree_list;
+pub mod tree_list;
@@ -1,4 +1,4 @@
-use crossterm::event::{Event, KeyEvent};
+use crossterm::event::{Event, KeyCode, KeyEvent};
 use tui::widgets::{InteractionOutcome, InteractiveWidgetState};
 
 use super::state::TreeListState;
@@ -43,6 +43,19 @@ impl InteractiveWidgetState for TreeListState {
 
 impl TreeListState {
     fn handle_key(&mut self, key: KeyEvent) -> InteractionOutcome {
-        InteractionOutcome::Bubble
+        let page = self.viewport_height.get().max(1);
+        match key.code {
+            KeyCode::Up => self.focus_item(self.focused_item.saturating_sub(1)),
+            KeyCode::Down => self.focus_item(self.focused_item + 1),
+            KeyCode::PageUp => self.focus_item(self.focused_item.saturating_sub(page)),
+            KeyCode::PageDown => self.focus_item(self.focused_item + page),
+            KeyCode::Home => self.focus_item(0),
+            KeyCode::End => self.focus_item(usize::MAX),
+            KeyCode::Left => self.collapse_focused(),
+            KeyCode::Right => self.expand_focused(),
+            KeyCode::Enter | KeyCode::Char(' ') => self.toggle_focused(),
+            _ => return InteractionOutcome::Bubble,
+        }
+        InteractionOutcome::Consumed
     }
 }
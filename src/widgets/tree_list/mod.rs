@@ -2,3 +2,5 @@ pub mod state;
 pub mod widget;
 // #[cfg(feature = "crossterm")]
 pub mod interactive;
+
+pub use self::{state::TreeListState, widget::TreeList};
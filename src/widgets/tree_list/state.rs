@@ -1,21 +1,175 @@
-use std::collections::HashSet;
+use std::{cell::Cell, collections::HashSet};
+
+use crate::path_utils::{dir_name, file_name};
 
 #[derive(Debug, Default)]
 pub struct TreeListState {
     pub(super) changed: bool,
     pub(super) focused: bool,
+    // leaf paths, dirs are implied by the `/`s in them
+    pub(super) paths: Vec<String>,
     pub(super) collapsed_paths: HashSet<String>,
-    pub(super) scroll_offset: usize,
+    // updated when rendering, to keep the focused item in view
+    pub(super) scroll_offset: Cell<usize>,
+    pub(super) viewport_height: Cell<usize>,
+    // index into the visible rows
     pub(super) focused_item: usize,
 }
 
-// impl Default for TreeListState {
-//     fn default() -> Self {
-//         Self {
-//             changed: false,
-//             focused: false,
-//             collapsed_paths: Default::default(),
-//             scroll_position: 0,
-//         }
-//     }
-// }
+// A visible line of the tree
+#[derive(Debug, PartialEq)]
+pub struct TreeRow {
+    pub path: String,
+    pub depth: usize,
+    pub is_dir: bool,
+}
+
+impl TreeRow {
+    pub fn name(&self) -> &str {
+        file_name(&self.path)
+    }
+}
+
+impl TreeListState {
+    pub fn set_paths(&mut self, mut paths: Vec<String>) {
+        // by component, so everything in a dir stays together
+        paths.sort_by(|a, b| a.split('/').cmp(b.split('/')));
+        paths.dedup();
+        self.paths = paths;
+        self.focused_item = self.focused_item.min(self.rows().len().saturating_sub(1));
+    }
+
+    pub fn is_collapsed(&self, path: &str) -> bool {
+        self.collapsed_paths.contains(path)
+    }
+
+    // the dirs and leaves that aren't inside a collapsed dir, in display order
+    pub fn rows(&self) -> Vec<TreeRow> {
+        let mut rows: Vec<TreeRow> = vec![];
+        let mut prev_dirs: Vec<&str> = vec![];
+        for path in self.paths.iter() {
+            let dirs: Vec<_> = path
+                .match_indices('/')
+                .map(|(idx, _)| &path[..idx])
+                .collect();
+            let num_shared = dirs
+                .iter()
+                .zip(prev_dirs.iter())
+                .take_while(|(a, b)| a == b)
+                .count();
+            for (depth, dir) in dirs.iter().enumerate().skip(num_shared) {
+                if !self.has_collapsed_parent(dir) {
+                    rows.push(TreeRow {
+                        path: dir.to_string(),
+                        depth,
+                        is_dir: true,
+                    });
+                }
+            }
+            if !self.has_collapsed_parent(path) {
+                rows.push(TreeRow {
+                    path: path.clone(),
+                    depth: dirs.len(),
+                    is_dir: false,
+                });
+            }
+            prev_dirs = dirs;
+        }
+        rows
+    }
+
+    fn has_collapsed_parent(&self, path: &str) -> bool {
+        let mut dir = dir_name(path);
+        while !dir.is_empty() {
+            if self.collapsed_paths.contains(dir) {
+                return true;
+            }
+            dir = dir_name(dir);
+        }
+        false
+    }
+
+    pub(super) fn focus_item(&mut self, idx: usize) {
+        let last = self.rows().len().saturating_sub(1);
+        self.focused_item = idx.min(last);
+        self.changed = true;
+    }
+
+    // collapses the focused dir, or moves up to its parent when there's nothing to collapse
+    pub(super) fn collapse_focused(&mut self) {
+        let rows = self.rows();
+        let row = match rows.get(self.focused_item) {
+            Some(row) => row,
+            None => return,
+        };
+        if row.is_dir && !self.is_collapsed(&row.path) {
+            self.collapsed_paths.insert(row.path.clone());
+            self.changed = true;
+        } else if let Some(idx) = rows.iter().position(|r| r.path == dir_name(&row.path)) {
+            self.focus_item(idx);
+        }
+    }
+
+    pub(super) fn expand_focused(&mut self) {
+        if let Some(row) = self.rows().get(self.focused_item) {
+            self.changed |= self.collapsed_paths.remove(&row.path);
+        }
+    }
+
+    pub(super) fn toggle_focused(&mut self) {
+        match self.rows().get(self.focused_item) {
+            Some(row) if row.is_dir && self.is_collapsed(&row.path) => self.expand_focused(),
+            Some(row) if row.is_dir => self.collapse_focused(),
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{TreeListState, TreeRow};
+
+    fn row(path: &str, depth: usize, is_dir: bool) -> TreeRow {
+        TreeRow {
+            path: path.to_string(),
+            depth,
+            is_dir,
+        }
+    }
+
+    #[test]
+    fn builds_rows() {
+        let mut state = TreeListState::default();
+        state.set_paths(
+            [
+                "Show/Season 01/b.mkv",
+                "Show/Season 01.nfo",
+                "Show/Season 01/a.mkv",
+                "top.mkv",
+            ]
+            .map(ToString::to_string)
+            .to_vec(),
+        );
+        assert_eq!(
+            vec![
+                row("Show", 0, true),
+                row("Show/Season 01", 1, true),
+                row("Show/Season 01/a.mkv", 2, false),
+                row("Show/Season 01/b.mkv", 2, false),
+                row("Show/Season 01.nfo", 1, false),
+                row("top.mkv", 0, false),
+            ],
+            state.rows()
+        );
+
+        // collapsing a nested dir from inside it
+        state.focus_item(2);
+        state.collapse_focused();
+        assert_eq!(1, state.focused_item);
+        state.collapse_focused();
+        assert_eq!(4, state.rows().len());
+
+        state.toggle_focused();
+        assert_eq!(6, state.rows().len());
+    }
+}
@@ -1 +1,97 @@
-pub struct TreeList {}
+use tui::{
+    buffer::Buffer,
+    layout::Rect,
+    style::Style,
+    widgets::{Block, InteractiveWidget, Widget},
+};
+
+use super::state::TreeListState;
+
+type LeafStyle<'a> = Box<dyn Fn(&str) -> Style + 'a>;
+
+// Renders the visible rows of a `TreeListState`, dirs with an expand / collapse marker
+#[derive(Default)]
+pub struct TreeList<'a> {
+    block: Option<Block<'a>>,
+    dir_style: Style,
+    highlight_style: Style,
+    leaf_style: Option<LeafStyle<'a>>,
+}
+
+impl<'a> TreeList<'a> {
+    pub fn block(mut self, block: Block<'a>) -> Self {
+        self.block = Some(block);
+        self
+    }
+
+    pub fn dir_style(mut self, style: Style) -> Self {
+        self.dir_style = style;
+        self
+    }
+
+    // applied to the focused row while the tree is focused
+    pub fn highlight_style(mut self, style: Style) -> Self {
+        self.highlight_style = style;
+        self
+    }
+
+    // style of each leaf, given its path
+    pub fn leaf_style(mut self, f: impl Fn(&str) -> Style + 'a) -> Self {
+        self.leaf_style = Some(Box::new(f));
+        self
+    }
+}
+
+impl<'a> InteractiveWidget for TreeList<'a> {
+    type State = TreeListState;
+
+    fn render(mut self, area: Rect, buf: &mut Buffer, state: &Self::State) {
+        let area = match self.block.take() {
+            Some(block) => {
+                let inner = block.inner(area);
+                block.render(area, buf);
+                inner
+            }
+            None => area,
+        };
+        if area.height == 0 {
+            return;
+        }
+
+        // scroll just enough to keep the focused row in view
+        let rows = state.rows();
+        let height = area.height as usize;
+        let focused_item = state.focused_item.min(rows.len().saturating_sub(1));
+        let mut offset = state.scroll_offset.get().min(focused_item);
+        if focused_item >= offset + height {
+            offset = focused_item + 1 - height;
+        }
+        state.scroll_offset.set(offset);
+        state.viewport_height.set(height);
+
+        for (idx, row) in rows.iter().enumerate().skip(offset).take(height) {
+            let y = area.y + (idx - offset) as u16;
+            let marker = match (row.is_dir, state.is_collapsed(&row.path)) {
+                (true, true) => "▸ ",
+                (true, false) => "▾ ",
+                (false, _) => "  ",
+            };
+            let style = match &self.leaf_style {
+                _ if row.is_dir => self.dir_style,
+                Some(leaf_style) => leaf_style(&row.path),
+                None => Style::default(),
+            };
+            let text = format!("{}{}{}", "  ".repeat(row.depth), marker, row.name());
+            buf.set_stringn(area.x, y, text, area.width as usize, style);
+            if state.focused && idx == focused_item {
+                let line = Rect {
+                    x: area.x,
+                    y,
+                    width: area.width,
+                    height: 1,
+                };
+                buf.set_style(line, self.highlight_style);
+            }
+        }
+    }
+}